@@ -0,0 +1,62 @@
+use crate::engine_interaction::TimeInfo;
+use map_model::{IntersectionID, LaneID, Map, TrafficBehavior};
+use specs::prelude::*;
+use specs::shrev::EventChannel;
+use std::collections::HashMap;
+
+/// Things happening in the simulation that scripts can react to
+#[derive(Debug, Clone, Copy)]
+pub enum SimEvent {
+    /// The vehicle reached the end of its itinerary
+    VehicleArrived(Entity),
+    VehicleParked(Entity),
+    VehicleUnparked(Entity),
+    /// The pedestrian reached the end of its itinerary
+    PedestrianArrived(Entity),
+    /// The light of an incoming lane changed
+    LightChanged {
+        inter: IntersectionID,
+        lane: LaneID,
+        behavior: TrafficBehavior,
+    },
+}
+
+/// Emits the `LightChanged` events, lights change at most once per simulated second
+#[derive(Default)]
+pub struct LightEventSystem {
+    last_seconds: Option<u64>,
+    /// Behavior of each light at the last check, lights can also be changed by scripts
+    last: HashMap<LaneID, TrafficBehavior>,
+}
+
+impl<'a> System<'a> for LightEventSystem {
+    type SystemData = (
+        Read<'a, Map>,
+        Read<'a, TimeInfo>,
+        Write<'a, EventChannel<SimEvent>>,
+    );
+
+    fn run(&mut self, (map, time, mut events): Self::SystemData) {
+        let now = time.time_seconds;
+        if self.last_seconds.replace(now) == Some(now) {
+            return;
+        }
+
+        let mut cur = HashMap::with_capacity(self.last.len());
+        for l in map.lanes().values() {
+            if !l.control.is_light() {
+                continue;
+            }
+            let behavior = l.control.get_behavior(now);
+            cur.insert(l.id, behavior);
+            if matches!(self.last.get(&l.id), Some(&x) if x != behavior) {
+                events.single_write(SimEvent::LightChanged {
+                    inter: l.dst,
+                    lane: l.id,
+                    behavior,
+                });
+            }
+        }
+        self.last = cur;
+    }
+}
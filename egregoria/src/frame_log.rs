@@ -1,5 +1,7 @@
 use std::sync::{Mutex, MutexGuard};
 
+/// Timings and messages of the current frame. Systems running in parallel log through a shared
+/// reference, so their lines can come in any order.
 #[derive(Default)]
 pub struct FrameLog {
     logs: Mutex<Vec<String>>,
@@ -2,8 +2,12 @@ use crate::engine_interaction::{MouseInfo, RenderStats, TimeInfo};
 use crate::frame_log::FrameLog;
 use crate::interaction::{InspectedEntity, RoadBuildResource, Tool};
 use crate::pedestrians::{spawn_pedestrian, PedestrianComponent};
+use crate::stats::{SafetyLog, TrafficStats};
 use crate::utils::delete_entity;
-use crate::vehicles::{spawn_parked_vehicle, VehicleComponent};
+use crate::vehicles::{
+    spawn_parked_vehicle, Deliveries, Emergencies, GridlockPolicy, Gridlocks, ODMatrix,
+    VehicleComponent,
+};
 use imgui::{im_str, StyleVar};
 use imgui::{Ui, Window};
 use imgui_inspect::{InspectArgsStruct, InspectRenderStruct};
@@ -44,6 +48,26 @@ impl AsRef<str> for AutoSaveEvery {
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeatmapMetric {
+    /// Mean speed compared to the cruising speed
+    SpeedRatio,
+    /// Vehicles per 100m
+    Density,
+    /// Time lost crossing the lane at the observed mean speed compared to cruising speed
+    Delay,
+}
+
+impl AsRef<str> for HeatmapMetric {
+    fn as_ref(&self) -> &str {
+        match self {
+            HeatmapMetric::SpeedRatio => "Speed ratio",
+            HeatmapMetric::Density => "Density",
+            HeatmapMetric::Delay => "Delay",
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Gui {
@@ -52,6 +76,10 @@ pub struct Gui {
     pub show_tips: bool,
     pub show_debug_layers: bool,
     pub show_scenarios: bool,
+    pub show_mods: bool,
+    pub show_traffic_stats: bool,
+    pub show_heatmap: bool,
+    pub heatmap_metric: HeatmapMetric,
     pub auto_save_every: AutoSaveEvery,
     #[serde(skip)]
     pub last_save: Instant,
@@ -61,6 +89,7 @@ pub struct Gui {
     pub n_pedestrians: i32,
 }
 
+/// Paths of the built-in scenarios followed by the ones of the active mods
 fn available_scenarios() -> Vec<String> {
     let mut available_scenarios = vec![];
     for file in std::fs::read_dir("lua/scenarios")
@@ -68,8 +97,13 @@ fn available_scenarios() -> Vec<String> {
         .flatten()
         .filter_map(|x| x.ok())
     {
-        available_scenarios.push(file.file_name().to_string_lossy().into_owned());
+        available_scenarios.push(file.path().to_string_lossy().into_owned());
     }
+    available_scenarios.extend(
+        mods::scenario_scripts()
+            .into_iter()
+            .map(|p| p.to_string_lossy().into_owned()),
+    );
     available_scenarios
 }
 
@@ -81,6 +115,10 @@ impl Default for Gui {
             show_tips: false,
             show_debug_layers: false,
             show_scenarios: false,
+            show_mods: false,
+            show_traffic_stats: false,
+            show_heatmap: false,
+            heatmap_metric: HeatmapMetric::SpeedRatio,
             auto_save_every: AutoSaveEvery::OneMinute,
             last_save: Instant::now(),
             available_scenarios: available_scenarios(),
@@ -109,6 +147,10 @@ impl Gui {
         self.auto_save(world);
 
         self.scenario(ui, world);
+
+        self.mods(ui);
+
+        self.traffic_stats(ui, world);
     }
 
     pub fn scenario(&mut self, ui: &Ui, world: &mut World) {
@@ -122,10 +164,7 @@ impl Gui {
             .build(&ui, || {
                 for scenario in scenarios.iter() {
                     if ui.small_button(&im_str!("{}", scenario)) {
-                        crate::lua::scenario_runner::set_scenario(
-                            world,
-                            &format!("lua/scenarios/{}", scenario),
-                        );
+                        crate::lua::scenario_runner::set_scenario(world, scenario);
                     }
                 }
                 if ui.small_button(im_str!("reload scenario list")) {
@@ -134,6 +173,177 @@ impl Gui {
             });
     }
 
+    pub fn mods(&mut self, ui: &Ui) {
+        if !self.show_mods {
+            return;
+        }
+        let scenarios = &mut self.available_scenarios;
+        Window::new(im_str!("Mods"))
+            .position([300.0, 300.0], imgui::Condition::FirstUseEver)
+            .opened(&mut self.show_mods)
+            .build(&ui, || {
+                let mut changed = false;
+                for p in mods::packages() {
+                    let mut enabled = p.enabled;
+                    if ui.checkbox(
+                        &im_str!("{} {}", p.manifest.name, p.manifest.version),
+                        &mut enabled,
+                    ) {
+                        mods::set_mod_enabled(&p.manifest.name, enabled);
+                        changed = true;
+                    }
+                    if !p.manifest.dependencies.is_empty() {
+                        ui.text(im_str!(
+                            "    depends on {}",
+                            p.manifest.dependencies.join(", ")
+                        ));
+                    }
+                    if p.missing_dependencies {
+                        ui.text_colored(
+                            [1.0, 0.3, 0.3, 1.0],
+                            im_str!("    missing or disabled dependencies"),
+                        );
+                    }
+                }
+                if ui.small_button(im_str!("rescan mods")) {
+                    mods::rescan_packages();
+                    changed = true;
+                }
+                if changed {
+                    *scenarios = available_scenarios();
+                }
+                ui.text("Vehicle types are applied on restart");
+            });
+    }
+
+    pub fn traffic_stats(&mut self, ui: &Ui, world: &mut World) {
+        if !self.show_traffic_stats {
+            return;
+        }
+        let stats: &mut TrafficStats = &mut world.write_resource::<TrafficStats>();
+        let gridlocks: &mut Gridlocks = &mut world.write_resource::<Gridlocks>();
+        let safety: &mut SafetyLog = &mut world.write_resource::<SafetyLog>();
+        Window::new(im_str!("Traffic stats"))
+            .size([330.0, 400.0], imgui::Condition::FirstUseEver)
+            .position([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .opened(&mut self.show_traffic_stats)
+            .build(&ui, || {
+                ui.set_next_item_width(70.0);
+                imgui::DragFloat::new(&ui, im_str!("interval (s)"), &mut stats.interval)
+                    .min(1.0)
+                    .max(3600.0)
+                    .display_format(im_str!("%.0f"))
+                    .build();
+
+                if ui.small_button(im_str!("export csv")) {
+                    match stats
+                        .export_csv("stats")
+                        .and_then(|_| safety.export_csv("stats"))
+                    {
+                        Ok(()) => info!("traffic stats exported to stats/"),
+                        Err(e) => error!("could not export traffic stats: {}", e),
+                    }
+                }
+                ui.same_line(0.0);
+                if ui.small_button(im_str!("clear")) {
+                    stats.clear();
+                    safety.clear();
+                }
+
+                ui.separator();
+                let n_trips = stats.trips.len();
+                ui.text(im_str!("{} trips", n_trips));
+                if n_trips > 0 {
+                    let total: f32 = stats.trips.iter().map(|t| t.travel_time()).sum();
+                    ui.text(im_str!("Mean travel time: {:.1}s", total / n_trips as f32));
+                }
+
+                ui.separator();
+                ui.text(im_str!(
+                    "Gridlocks: {} now, {} total",
+                    gridlocks.cycles.len(),
+                    stats.gridlocks.len()
+                ));
+                for policy in &[
+                    GridlockPolicy::Ignore,
+                    GridlockPolicy::Reroute,
+                    GridlockPolicy::Despawn,
+                ] {
+                    if ui.radio_button_bool(
+                        &im_str!("{}", policy.as_ref()),
+                        gridlocks.policy == *policy,
+                    ) {
+                        gridlocks.policy = *policy;
+                    }
+                    ui.same_line(0.0);
+                }
+                ui.new_line();
+                ui.set_next_item_width(70.0);
+                imgui::DragFloat::new(&ui, im_str!("min wait (s)"), &mut gridlocks.min_wait)
+                    .min(1.0)
+                    .max(600.0)
+                    .display_format(im_str!("%.0f"))
+                    .build();
+
+                ui.separator();
+                ui.text(im_str!(
+                    "Collisions: {}, near-misses: {}",
+                    safety.collisions(),
+                    safety.near_misses()
+                ));
+                ui.set_next_item_width(70.0);
+                imgui::DragFloat::new(&ui, im_str!("near-miss TTC (s)"), &mut safety.near_miss_ttc)
+                    .min(0.1)
+                    .max(5.0)
+                    .speed(0.05)
+                    .display_format(im_str!("%.1f"))
+                    .build();
+
+                let (start, end) = unwrap_or!(stats.last_interval(), {
+                    ui.text("Waiting for the first interval to end");
+                    return;
+                });
+                ui.separator();
+                ui.text(im_str!("Interval {:.0}s - {:.0}s", start, end));
+
+                let mut lanes: Vec<_> = stats.last_lanes().collect();
+                let (vehicle_flow, speed_flow) = lanes.iter().fold((0.0, 0.0), |(f, s), l| {
+                    (f + l.flow, s + l.flow * l.mean_speed)
+                });
+                if vehicle_flow > 0.0 {
+                    ui.text(im_str!(
+                        "Network mean speed: {:.1}m/s",
+                        speed_flow / vehicle_flow
+                    ));
+                }
+
+                ui.text("Busiest lanes");
+                lanes.sort_by(|a, b| b.flow.partial_cmp(&a.flow).unwrap());
+                for l in lanes.iter().take(5) {
+                    ui.text(im_str!(
+                        "  {:>6.0}veh/h {:>5.1}m/s {:>4.0}% occupied",
+                        l.flow,
+                        l.mean_speed,
+                        l.occupancy * 100.0
+                    ));
+                }
+
+                ui.text("Most delayed intersections");
+                let mut inters: Vec<_> = stats.last_intersections().collect();
+                inters.sort_by(|a, b| b.total_delay.partial_cmp(&a.total_delay).unwrap());
+                for i in inters.iter().take(5) {
+                    ui.text(im_str!(
+                        "  ({:.0}, {:.0}) delay {:.1}s/veh queue {:.1} (max {})",
+                        i.pos.x,
+                        i.pos.y,
+                        i.mean_delay,
+                        i.mean_queue,
+                        i.max_queue
+                    ));
+                }
+            });
+    }
+
     pub fn auto_save(&mut self, world: &mut World) {
         if let Some(every) = self.auto_save_every.into() {
             let now = Instant::now();
@@ -156,7 +366,7 @@ impl Gui {
         let toolbox_w = 120.0;
 
         Window::new(im_str!("Toolbox"))
-            .size([toolbox_w, 30.0 * 5.0 + 20.0], imgui::Condition::Always)
+            .size([toolbox_w, 30.0 * 6.0 + 20.0], imgui::Condition::Always)
             .position([w - toolbox_w, h * 0.5 - 30.0], imgui::Condition::Always)
             .scroll_bar(false)
             .title_bar(true)
@@ -172,6 +382,7 @@ impl Gui {
                     (im_str!("Curved Road"), Tool::RoadbuildCurved),
                     (im_str!("Road Editor"), Tool::RoadEditor),
                     (im_str!("Bulldozer"), Tool::Bulldozer),
+                    (im_str!("Dispatch"), Tool::Dispatch),
                 ];
 
                 for (name, tool) in &tools {
@@ -223,6 +434,64 @@ impl Gui {
                 });
         }
 
+        if matches!(*world.read_resource::<Tool>(), Tool::Dispatch) {
+            Window::new(im_str!("Dispatch"))
+                .size([250.0, 200.0], imgui::Condition::Always)
+                .position(
+                    [w - 250.0 - toolbox_w, h * 0.5 - 30.0],
+                    imgui::Condition::Always,
+                )
+                .title_bar(true)
+                .movable(false)
+                .collapsible(false)
+                .resizable(false)
+                .build(&ui, || {
+                    let emergencies: &mut Emergencies = &mut world.write_resource::<Emergencies>();
+
+                    if emergencies.station.is_none() {
+                        ui.text("Click to place the station");
+                        return;
+                    }
+                    ui.text("Click to send an emergency vehicle");
+                    if ui.small_button(im_str!("move station")) {
+                        emergencies.station = None;
+                    }
+                    ui.same_line(0.0);
+                    if ui.small_button(im_str!("clear calls")) {
+                        emergencies
+                            .calls
+                            .retain(|c| c.arrived.is_none() && c.vehicle.is_some());
+                    }
+
+                    match emergencies.mean_response_time() {
+                        Some(t) => ui.text(im_str!("Mean response time: {:.1}s", t)),
+                        None => ui.text("Mean response time: -"),
+                    }
+
+                    ui.separator();
+                    for call in emergencies.calls.iter().rev().take(8) {
+                        match (call.response_time(), call.vehicle) {
+                            (Some(t), _) => ui.text(im_str!(
+                                "{:.0} {:.0}: arrived in {:.1}s",
+                                call.target.x,
+                                call.target.y,
+                                t
+                            )),
+                            (None, Some(_)) => ui.text(im_str!(
+                                "{:.0} {:.0}: en route",
+                                call.target.x,
+                                call.target.y
+                            )),
+                            (None, None) => ui.text(im_str!(
+                                "{:.0} {:.0}: cancelled",
+                                call.target.x,
+                                call.target.y
+                            )),
+                        }
+                    }
+                });
+        }
+
         tok.pop(ui);
     }
 
@@ -316,12 +585,21 @@ impl Gui {
                 if imgui::MenuItem::new(im_str!("Scenarios")).build(&ui) {
                     self.show_scenarios = true;
                 }
+                if imgui::MenuItem::new(im_str!("Mods")).build(&ui) {
+                    self.show_mods = true;
+                }
+                if imgui::MenuItem::new(im_str!("Traffic Stats")).build(&ui) {
+                    self.show_traffic_stats = true;
+                }
                 if imgui::MenuItem::new(im_str!("Debug Info")).build(&ui) {
                     self.show_debug_info = true;
                 }
                 if imgui::MenuItem::new(im_str!("Debug Layers")).build(&ui) {
                     self.show_debug_layers = true;
                 }
+                if imgui::MenuItem::new(im_str!("Heatmap")).build(&ui) {
+                    self.show_heatmap = true;
+                }
             });
             ui.menu(im_str!("Settings"), true, || {
                 ui.text("Auto save every");
@@ -413,6 +691,83 @@ impl Gui {
                     }
                 }
 
+                if ui.small_button(im_str!("import OD matrix")) {
+                    let od = ODMatrix::from_csv(
+                        "od/zones.csv",
+                        "od/matrix.csv",
+                        &world.read_resource::<Map>(),
+                    );
+                    match od {
+                        Ok(od) => *world.write_resource::<ODMatrix>() = od,
+                        Err(e) => error!("could not import OD matrix: {}", e),
+                    }
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Reads od/zones.csv and od/matrix.csv");
+                }
+
+                {
+                    let od = &mut *world.write_resource::<ODMatrix>();
+                    if !od.pairs.is_empty() {
+                        ui.same_line(0.0);
+                        if ui.small_button(im_str!("clear")) {
+                            *od = ODMatrix::default();
+                        }
+                        ui.text(im_str!(
+                            "{} zones, {:.0} trips/h, {} spawned, {} completed, {} failed",
+                            od.zones.len(),
+                            od.trips_per_hour(),
+                            od.spawned,
+                            od.completed,
+                            od.failed
+                        ));
+                    }
+                }
+
+                {
+                    let deliveries = &mut *world.write_resource::<Deliveries>();
+                    ui.set_next_item_width(70.0);
+                    imgui::DragFloat::new(
+                        &ui,
+                        im_str!("delivery tours/h"),
+                        &mut deliveries.tours_per_hour,
+                    )
+                    .min(0.0)
+                    .max(1000.0)
+                    .display_format(im_str!("%.0f"))
+                    .build();
+
+                    let mut stops = deliveries.stops_per_tour as i32;
+                    ui.set_next_item_width(70.0);
+                    imgui::DragInt::new(&ui, im_str!("stops per tour"), &mut stops)
+                        .min(1)
+                        .max(30)
+                        .build();
+                    deliveries.stops_per_tour = stops.max(1) as u32;
+
+                    ui.set_next_item_width(70.0);
+                    imgui::DragFloat::new(&ui, im_str!("dwell (s)"), &mut deliveries.dwell_time)
+                        .min(0.0)
+                        .max(1800.0)
+                        .display_format(im_str!("%.0f"))
+                        .build();
+
+                    ui.checkbox(im_str!("double park"), &mut deliveries.double_park);
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Stop on the driving lane instead of using kerb spots");
+                    }
+
+                    if deliveries.started > 0 {
+                        ui.text(im_str!(
+                            "{} tours, {} completed, {} stops, {:.0}s double parked",
+                            deliveries.started,
+                            deliveries.completed,
+                            deliveries.stops_made,
+                            deliveries.double_parked_time
+                        ));
+                    }
+                }
+
                 let map: &mut Map = &mut world.write_resource::<Map>();
 
                 if ui.small_button(im_str!("load Paris map")) {
@@ -0,0 +1,37 @@
+use crate::engine_interaction::{MouseButton, MouseInfo};
+use crate::interaction::Tool;
+use crate::vehicles::{dispatch_emergency, Emergencies};
+use specs::prelude::*;
+
+/// Places the emergency station, then sends an emergency vehicle wherever the user clicks
+pub struct DispatchSystem;
+
+#[derive(SystemData)]
+pub struct DispatchData<'a> {
+    tool: Read<'a, Tool>,
+    mouseinfo: Read<'a, MouseInfo>,
+    lazy: Read<'a, LazyUpdate>,
+    emergencies: Write<'a, Emergencies>,
+}
+
+impl<'a> System<'a> for DispatchSystem {
+    type SystemData = DispatchData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        if !matches!(*data.tool, Tool::Dispatch)
+            || !data.mouseinfo.just_pressed.contains(&MouseButton::Left)
+        {
+            return;
+        }
+
+        let pos = data.mouseinfo.unprojected;
+        if data.emergencies.station.is_none() {
+            data.emergencies.station = Some(pos);
+            return;
+        }
+
+        data.lazy.exec_mut(move |world| {
+            dispatch_emergency(world, pos);
+        });
+    }
+}
@@ -1,4 +1,5 @@
 pub use self::bulldozer::*;
+pub use self::dispatch::*;
 pub use self::follow::*;
 pub use self::inspected_aura::*;
 pub use self::movable::*;
@@ -7,6 +8,7 @@ pub use self::roadeditor::*;
 pub use self::selectable::*;
 
 mod bulldozer;
+mod dispatch;
 mod follow;
 mod inspected_aura;
 mod movable;
@@ -21,6 +23,7 @@ pub enum Tool {
     RoadbuildCurved,
     RoadEditor,
     Bulldozer,
+    Dispatch,
 }
 
 const Z_TOOL: f32 = 0.9;
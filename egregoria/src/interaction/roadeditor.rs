@@ -96,7 +96,7 @@ impl<'a> System<'a> for RoadEditorSystem {
                         IntersectionComponent {
                             id,
                             turn_policy: inter.turn_policy,
-                            light_policy: inter.light_policy,
+                            light_policy: inter.light_policy.clone(),
                         },
                     )
                     .unwrap(); // Unwrap ok: inspect_e is never deleted
@@ -108,7 +108,7 @@ impl<'a> System<'a> for RoadEditorSystem {
             let selected_interc = data.intersections.get(state.inspect_e).unwrap(); // Unwrap ok: defined in new
             data.map.update_intersection(selected_interc.id, |inter| {
                 inter.turn_policy = selected_interc.turn_policy;
-                inter.light_policy = selected_interc.light_policy;
+                inter.light_policy = selected_interc.light_policy.clone();
             });
         }
     }
@@ -3,21 +3,29 @@
 #![allow(clippy::too_many_arguments)]
 
 use crate::engine_interaction::{KeyboardInfo, RenderStats, TimeInfo};
+use crate::events::{LightEventSystem, SimEvent};
 use crate::gui::Gui;
 use crate::interaction::{
-    BulldozerResource, BulldozerSystem, DeletedEvent, FollowEntity, InspectedAuraSystem,
-    InspectedEntity, MovableSystem, MovedEvent, RoadEditorResource, RoadEditorSystem,
-    SelectableSystem,
+    BulldozerResource, BulldozerSystem, DeletedEvent, DispatchSystem, FollowEntity,
+    InspectedAuraSystem, InspectedEntity, MovableSystem, MovedEvent, RoadEditorResource,
+    RoadEditorSystem, SelectableSystem,
 };
 use crate::interaction::{IntersectionComponent, RoadBuildResource, RoadBuildSystem};
-use crate::map_interaction::{ItinerarySystem, ParkingManagement};
-use crate::pedestrians::PedestrianDecision;
+use crate::map_interaction::{ItinerarySystem, LightScriptSystem, ParkingManagement};
+use crate::pedestrians::{CrosswalkOccupancy, CrosswalkSystem, PedestrianDecision};
 use crate::physics::systems::KinematicsApply;
 use crate::physics::CollisionWorld;
 use crate::physics::{Collider, Transform};
-use crate::rendering::assets::AssetRender;
+use crate::rendering::assets::{AssetRegistry, AssetRender};
 use crate::rendering::meshrender_component::MeshRender;
+use crate::stats::{SafetyLog, SafetySystem, TrafficStats, TrafficStatsSystem};
 use crate::vehicles::systems::VehicleDecision;
+use crate::vehicles::{
+    Deliveries, DeliverySystem, Emergencies, EmergencySystem, GridlockSystem, Gridlocks,
+    IntersectionReservations, ODMatrix, ODSpawnSystem, ReservationSystem, StopSignQueues,
+    StopSignSystem, VehicleTypes,
+};
+use specs::rayon::{ThreadPool, ThreadPoolBuilder};
 use specs::shrev::EventChannel;
 use specs::world::EntitiesRes;
 use specs::{Dispatcher, DispatcherBuilder, LazyUpdate, World, WorldExt};
@@ -35,6 +43,7 @@ pub mod frame_log;
 pub mod gui;
 
 pub mod engine_interaction;
+pub mod events;
 pub mod interaction;
 pub mod lua;
 pub mod map_interaction;
@@ -43,16 +52,32 @@ pub mod physics;
 pub mod rand_provider;
 pub mod rendering;
 mod saveload;
+pub mod stats;
 pub mod vehicles;
 
 use crate::frame_log::FrameLog;
-use crate::lua::scenario_runner::{RunningScenario, RunningScenarioSystem};
+use crate::lua::scenario_runner::{run_scenario, RunningScenario};
 use crate::rendering::immediate::ImmediateDraw;
 pub use imgui;
 use map_model::{Map, SerializedMap};
 pub use rand_provider::RandProvider;
 pub use specs;
 use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// How the systems are run each tick
+#[derive(Clone)]
+pub enum DispatchMode {
+    /// Systems that don't share resources run concurrently
+    Parallel,
+    /// Like `Parallel`, on a pool shared with other simulations of the same process
+    Shared(Arc<ThreadPool>),
+    /// Everything runs on a single thread, including the parallel joins inside systems,
+    /// and randomness comes from the seeded `RandProvider`.
+    /// The headless cli's `--check-determinism` verifies that runs are reproducible.
+    Sequential,
+}
 
 pub struct EgregoriaState {
     pub world: World,
@@ -65,13 +90,18 @@ impl EgregoriaState {
     pub fn run(&mut self) {
         self.world.read_resource::<FrameLog>().clear();
         let t = std::time::Instant::now();
-        self.dispatcher.dispatch_seq(&self.world);
+        self.dispatcher.dispatch_par(&self.world);
         self.dispatcher.dispatch_thread_local(&self.world);
+        run_scenario(&mut self.world);
         self.world.maintain();
         self.world.write_resource::<RenderStats>().update_time = t.elapsed().as_secs_f32();
     }
 
     pub fn init() -> EgregoriaState {
+        Self::init_with(DispatchMode::Parallel)
+    }
+
+    pub fn init_with(mode: DispatchMode) -> EgregoriaState {
         let mut world = World::empty();
 
         info!("Seed is {}", RNG_SEED);
@@ -90,6 +120,26 @@ impl EgregoriaState {
         world.insert(FrameLog::default());
         world.insert(RunningScenario::default());
         world.insert(ImmediateDraw::default());
+        world.insert(TrafficStats::default());
+        world.insert(SafetyLog::default());
+        world.insert(ODMatrix::default());
+        world.insert(Gridlocks::default());
+        world.insert(Emergencies::default());
+        world.insert(Deliveries::default());
+        world.insert(CrosswalkOccupancy::default());
+        world.insert(StopSignQueues::default());
+        world.insert(IntersectionReservations::default());
+        world.insert(UnreadableMap::default());
+
+        for name in saveload::load_or_default::<Vec<String>>("disabled_mods") {
+            mods::set_mod_enabled(&name, false);
+        }
+
+        let mut assets = AssetRegistry::default();
+        let mut vehicle_scripts = vec![PathBuf::from("lua/vehicles.lua")];
+        vehicle_scripts.extend(mods::vehicle_scripts());
+        world.insert(VehicleTypes::load(&vehicle_scripts, &mut assets));
+        world.insert(assets);
 
         world.register::<Transform>();
         world.register::<Collider>();
@@ -100,6 +150,7 @@ impl EgregoriaState {
         // Event channels init
         world.insert(EventChannel::<MovedEvent>::new());
         world.insert(EventChannel::<DeletedEvent>::new());
+        world.insert(EventChannel::<SimEvent>::new());
 
         // Systems state init
         let s = RoadBuildResource::new(&mut world);
@@ -112,15 +163,51 @@ impl EgregoriaState {
         world.insert(s);
 
         // Dispatcher init
-        let mut dispatcher = DispatcherBuilder::new()
+        let mut builder = DispatcherBuilder::new();
+        match mode {
+            DispatchMode::Parallel => {}
+            DispatchMode::Shared(pool) => builder = builder.with_pool(pool),
+            DispatchMode::Sequential => {
+                let pool = ThreadPoolBuilder::new()
+                    .num_threads(1)
+                    .build()
+                    .expect("could not create the simulation thread");
+                builder = builder.with_pool(Arc::new(pool));
+            }
+        }
+
+        let mut dispatcher = builder
             .with(SelectableSystem, "selectable", &[])
             .with(RoadBuildSystem, "rgs", &[])
             .with(RoadEditorSystem, "res", &[])
             .with(BulldozerSystem, "bull", &[])
+            .with(DispatchSystem, "dispatch", &[])
             .with(ItinerarySystem, "itinerary", &["rgs", "res", "bull"])
-            .with(VehicleDecision, "car", &["itinerary"])
+            .with(
+                LightScriptSystem::default(),
+                "light scripts",
+                &["itinerary"],
+            )
+            .with(CrosswalkSystem, "crosswalks", &["itinerary"])
+            .with(StopSignSystem, "stop signs", &["itinerary"])
+            .with(ReservationSystem, "reservations", &["itinerary"])
+            .with(
+                VehicleDecision,
+                "car",
+                &[
+                    "itinerary",
+                    "light scripts",
+                    "crosswalks",
+                    "stop signs",
+                    "reservations",
+                ],
+            )
             .with(PedestrianDecision, "pedestrian", &["itinerary"])
-            .with(RunningScenarioSystem, "scenario", &[])
+            .with(ODSpawnSystem, "od spawn", &["car"])
+            .with(GridlockSystem::default(), "gridlock", &["car"])
+            .with(EmergencySystem, "emergency", &["car"])
+            .with(DeliverySystem, "delivery", &["car"])
+            .with(LightEventSystem::default(), "light events", &[])
             .with(
                 MovableSystem::default(),
                 "movable",
@@ -131,6 +218,12 @@ impl EgregoriaState {
                 "speed apply",
                 &["movable"],
             )
+            .with(
+                TrafficStatsSystem::default(),
+                "traffic stats",
+                &["speed apply"],
+            )
+            .with(SafetySystem::default(), "safety", &["speed apply"])
             .with(
                 InspectedAuraSystem::default(),
                 "selectable aura",
@@ -144,8 +237,19 @@ impl EgregoriaState {
     }
 }
 
+/// Set when the saved map couldn't be read, so that saving doesn't replace it
+#[derive(Default)]
+pub struct UnreadableMap(pub bool);
+
 pub fn load_from_disk(world: &mut World) {
-    let map: Map = saveload::load_or_default::<map_model::SerializedMap>("map").into();
+    let map: Map = match saveload::load_map("map") {
+        Ok(map) => map.unwrap_or_default().into(),
+        Err(e) => {
+            error!("{}, starting from an empty map", e);
+            world.insert(UnreadableMap(true));
+            SerializedMap::default().into()
+        }
+    };
     world.insert(map);
     vehicles::setup(world);
     pedestrians::setup(world);
@@ -156,6 +260,16 @@ pub fn load_from_disk(world: &mut World) {
 pub fn save_to_disk(world: &mut World) {
     let _ = std::io::stdout().flush();
     crate::saveload::save(&*world.read_resource::<Gui>(), "gui");
+    let disabled_mods: Vec<String> = mods::packages()
+        .into_iter()
+        .filter(|p| !p.enabled)
+        .map(|p| p.manifest.name)
+        .collect();
+    crate::saveload::save(&disabled_mods, "disabled_mods");
     crate::vehicles::save(world);
-    crate::saveload::save(&SerializedMap::from(&*world.read_resource::<Map>()), "map");
+    if world.read_resource::<UnreadableMap>().0 {
+        error!("not saving the map, to keep the one that couldn't be loaded");
+        return;
+    }
+    crate::saveload::save_map(&SerializedMap::from(&*world.read_resource::<Map>()), "map");
 }
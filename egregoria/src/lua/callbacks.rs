@@ -0,0 +1,96 @@
+use super::map::{LuaIntersection, LuaLane};
+use super::LuaEntity;
+use crate::engine_interaction::TimeInfo;
+use crate::events::SimEvent;
+use crate::interaction::DeletedEvent;
+use map_model::TrafficBehavior;
+use mods::mlua::Lua;
+use specs::shrev::EventChannel;
+use specs::{Entity, ReaderId, World, WorldExt};
+
+fn behavior_name(b: TrafficBehavior) -> &'static str {
+    match b {
+        TrafficBehavior::RED => "red",
+        TrafficBehavior::ORANGE => "orange",
+        TrafficBehavior::GREEN => "green",
+        TrafficBehavior::STOP => "stop",
+        TrafficBehavior::RESERVATION => "reservation",
+    }
+}
+
+/// Forwards the simulation events to the global functions of a script, the ones it doesn't
+/// define are skipped:
+/// `OnTick(dt)`, `OnVehicleArrived(e)`, `OnVehicleParked(e)`, `OnVehicleUnparked(e)`,
+/// `OnPedestrianArrived(e)`, `OnEntityDeleted(e)` and `OnLightChanged(inter, lane, state)`
+pub struct LuaCallbacks {
+    sim: ReaderId<SimEvent>,
+    deleted: ReaderId<DeletedEvent>,
+}
+
+impl LuaCallbacks {
+    /// Only the events happening after this call are forwarded
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            sim: world
+                .write_resource::<EventChannel<SimEvent>>()
+                .register_reader(),
+            deleted: world
+                .write_resource::<EventChannel<DeletedEvent>>()
+                .register_reader(),
+        }
+    }
+
+    /// Calls the functions for the events that happened since the last call,
+    /// with the `world` global bound to `world`
+    pub fn fire(&mut self, l: &Lua, world: &mut World) {
+        let dt = world.read_resource::<TimeInfo>().delta;
+        let sim: Vec<SimEvent> = world
+            .read_resource::<EventChannel<SimEvent>>()
+            .read(&mut self.sim)
+            .copied()
+            .collect();
+        let deleted: Vec<Entity> = world
+            .read_resource::<EventChannel<DeletedEvent>>()
+            .read(&mut self.deleted)
+            .map(|ev| ev.e)
+            .collect();
+
+        super::with_world(l, world, |l| {
+            mods::call_if_defined(l, "OnTick", dt);
+
+            for ev in sim {
+                match ev {
+                    SimEvent::VehicleArrived(e) => {
+                        mods::call_if_defined(l, "OnVehicleArrived", LuaEntity(e))
+                    }
+                    SimEvent::VehicleParked(e) => {
+                        mods::call_if_defined(l, "OnVehicleParked", LuaEntity(e))
+                    }
+                    SimEvent::VehicleUnparked(e) => {
+                        mods::call_if_defined(l, "OnVehicleUnparked", LuaEntity(e))
+                    }
+                    SimEvent::PedestrianArrived(e) => {
+                        mods::call_if_defined(l, "OnPedestrianArrived", LuaEntity(e))
+                    }
+                    SimEvent::LightChanged {
+                        inter,
+                        lane,
+                        behavior,
+                    } => mods::call_if_defined(
+                        l,
+                        "OnLightChanged",
+                        (
+                            LuaIntersection(inter),
+                            LuaLane(lane),
+                            behavior_name(behavior),
+                        ),
+                    ),
+                };
+            }
+
+            for e in deleted {
+                mods::call_if_defined(l, "OnEntityDeleted", LuaEntity(e));
+            }
+        });
+    }
+}
@@ -0,0 +1,165 @@
+use super::LuaWorld;
+use map_model::{
+    IntersectionID, LaneID, LanePattern, LanePatternBuilder, LightPolicy, Map, RoadID,
+    RoadSegmentKind,
+};
+use mods::mlua::{Error, Lua, Result, Table, UserData, UserDataMethods};
+use mods::LuaVec2;
+use specs::WorldExt;
+
+#[derive(Clone, Copy)]
+pub(crate) struct LuaIntersection(pub(crate) IntersectionID);
+
+impl UserData for LuaIntersection {}
+
+#[derive(Clone, Copy)]
+pub(crate) struct LuaRoad(pub(crate) RoadID);
+
+impl UserData for LuaRoad {}
+
+#[derive(Clone, Copy)]
+pub(crate) struct LuaLane(pub(crate) LaneID);
+
+impl UserData for LuaLane {}
+
+/// Reads a pattern table such as `{ lanes = 2, sidewalks = true, parking = false, one_way = false }`,
+/// missing fields take the defaults of the road building tool
+fn lane_pattern(t: Option<Table>) -> Result<LanePattern> {
+    let mut b = LanePatternBuilder::new();
+    if let Some(t) = t {
+        if let Some(n) = t.get::<_, Option<u32>>("lanes")? {
+            if n == 0 {
+                return Err(Error::RuntimeError(
+                    "a road needs at least one lane".to_string(),
+                ));
+            }
+            b = b.n_lanes(n);
+        }
+        if let Some(x) = t.get::<_, Option<bool>>("sidewalks")? {
+            b = b.sidewalks(x);
+        }
+        if let Some(x) = t.get::<_, Option<bool>>("parking")? {
+            b = b.parking(x);
+        }
+        if let Some(x) = t.get::<_, Option<bool>>("one_way")? {
+            b = b.one_way(x);
+        }
+    }
+    Ok(b.build())
+}
+
+/// `script` is the path of the Lua policy for `custom`
+fn light_policy(name: &str, script: Option<String>) -> Result<LightPolicy> {
+    Ok(match name {
+        "no_lights" => LightPolicy::NoLights,
+        "stop_signs" => LightPolicy::StopSigns,
+        "lights" => LightPolicy::Lights,
+        "smart" => LightPolicy::Smart,
+        "reservations" => LightPolicy::Reservations,
+        "custom" => LightPolicy::Custom(script.ok_or_else(|| {
+            Error::RuntimeError("the custom light policy needs a script".to_string())
+        })?),
+        _ => {
+            return Err(Error::RuntimeError(format!(
+                "unknown light policy {}, expected one of no_lights, stop_signs, lights, smart, reservations or custom",
+                name
+            )))
+        }
+    })
+}
+
+fn check_inter(map: &Map, i: LuaIntersection) -> Result<IntersectionID> {
+    if map.intersections().contains_key(i.0) {
+        Ok(i.0)
+    } else {
+        Err(Error::RuntimeError(
+            "intersection doesn't exist".to_string(),
+        ))
+    }
+}
+
+/// Map editing, so scenarios can build their own road layout in `Init`
+pub(super) fn add_map_methods<'lua, 'a, M: UserDataMethods<'lua, LuaWorld<'a>>>(methods: &mut M) {
+    methods.add_method(
+        "add_intersection",
+        |_: &Lua, sel: &LuaWorld, pos: LuaVec2| {
+            let w = sel.w.borrow();
+            let mut map = w.write_resource::<Map>();
+            Ok(LuaIntersection(map.add_intersection(pos.0)))
+        },
+    );
+
+    methods.add_method(
+        "connect",
+        |_: &Lua,
+         sel: &LuaWorld,
+         (src, dst, pattern): (LuaIntersection, LuaIntersection, Option<Table>)| {
+            let pattern = lane_pattern(pattern)?;
+            let w = sel.w.borrow();
+            let mut map = w.write_resource::<Map>();
+            let src = check_inter(&map, src)?;
+            let dst = check_inter(&map, dst)?;
+            if src == dst {
+                return Err(Error::RuntimeError(
+                    "cannot connect an intersection to itself".to_string(),
+                ));
+            }
+            Ok(LuaRoad(map.connect(
+                src,
+                dst,
+                &pattern,
+                RoadSegmentKind::Straight,
+            )))
+        },
+    );
+
+    methods.add_method(
+        "split_road",
+        |_: &Lua, sel: &LuaWorld, (road, pos): (LuaRoad, LuaVec2)| {
+            let w = sel.w.borrow();
+            let mut map = w.write_resource::<Map>();
+            if !map.roads().contains_key(road.0) {
+                return Err(Error::RuntimeError("road doesn't exist".to_string()));
+            }
+            Ok(LuaIntersection(map.split_road(road.0, pos.0)))
+        },
+    );
+
+    methods.add_method(
+        "set_light_policy",
+        |_: &Lua,
+         sel: &LuaWorld,
+         (inter, name, script): (LuaIntersection, String, Option<String>)| {
+            let policy = light_policy(&name, script)?;
+            let w = sel.w.borrow();
+            let mut map = w.write_resource::<Map>();
+            let id = check_inter(&map, inter)?;
+            map.update_intersection(id, |i| i.light_policy = policy.clone());
+            Ok(())
+        },
+    );
+
+    methods.add_method(
+        "set_turn_policy",
+        |_: &Lua, sel: &LuaWorld, (inter, t): (LuaIntersection, Table)| {
+            let w = sel.w.borrow();
+            let mut map = w.write_resource::<Map>();
+            let id = check_inter(&map, inter)?;
+
+            let mut policy = map.intersections()[id].turn_policy;
+            if let Some(x) = t.get::<_, Option<bool>>("left_turns")? {
+                policy.left_turns = x;
+            }
+            if let Some(x) = t.get::<_, Option<bool>>("back_turns")? {
+                policy.back_turns = x;
+            }
+            map.update_intersection(id, |i| i.turn_policy = policy);
+            Ok(())
+        },
+    );
+
+    methods.add_method("clear", |_: &Lua, sel: &LuaWorld, (): ()| {
+        sel.w.borrow().write_resource::<Map>().clear();
+        Ok(())
+    });
+}
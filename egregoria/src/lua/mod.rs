@@ -3,34 +3,51 @@ use crate::physics::Transform;
 use crate::rendering::immediate::ImmediateDraw;
 use crate::rendering::Color;
 use crate::utils::delete_entity;
-use crate::vehicles::{make_vehicle_entity, VehicleComponent, VehicleKind, VehicleState};
+use crate::vehicles::{
+    make_vehicle_entity, VehicleComponent, VehicleKind, VehicleState, VehicleTypes,
+};
 use geom::Vec2;
 use mods::mlua::{Lua, ToLua, UserData, UserDataMethods, Value};
 use mods::LuaVec2;
 use specs::{Entity, World, WorldExt};
+use std::cell::RefCell;
 
+mod callbacks;
+mod map;
+mod queries;
+mod scenario_map;
 pub mod scenario_runner;
 
-struct LuaWorld {
-    w: *mut World,
-}
+pub use callbacks::LuaCallbacks;
+pub use scenario_map::load_scenario_map;
+
+/// Borrow of the world shared by the `world` and `draw` globals, see `with_world`
+type WorldCell<'a> = RefCell<&'a mut World>;
 
-unsafe impl Send for LuaWorld {}
+struct LuaWorld<'a> {
+    w: &'a WorldCell<'a>,
+}
 
-impl UserData for LuaWorld {
+impl<'a> UserData for LuaWorld<'a> {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method(
             "add_car",
-            |_: &Lua, sel: &Self, (pos, dir, objective): (LuaVec2, LuaVec2, LuaVec2)| unsafe {
+            |_: &Lua, sel: &Self, (pos, dir, objective): (LuaVec2, LuaVec2, LuaVec2)| {
+                let mut w = sel.w.borrow_mut();
+                let kind = w
+                    .read_resource::<VehicleTypes>()
+                    .by_name("car")
+                    .unwrap_or(VehicleKind(0));
                 let e = make_vehicle_entity(
-                    &mut (*sel.w),
+                    &mut w,
                     Transform::new_cos_sin(pos.0, dir.0.try_normalize().unwrap_or(Vec2::UNIT_X)),
                     VehicleComponent {
                         ang_velocity: 0.0,
+                        steering_angle: 0.0,
                         wait_time: 0.0,
                         park_spot: None,
                         state: VehicleState::Driving,
-                        kind: VehicleKind::Car,
+                        kind,
                     },
                     Itinerary::simple(vec![objective.0]),
                     true,
@@ -39,31 +56,33 @@ impl UserData for LuaWorld {
             },
         );
 
-        methods.add_method("pos", |l: &Lua, sel: &Self, e: LuaEntity| unsafe {
-            Ok(match (*sel.w).read_storage::<Transform>().get(e.0) {
+        methods.add_method("pos", |l: &Lua, sel: &Self, e: LuaEntity| {
+            Ok(match sel.w.borrow().read_storage::<Transform>().get(e.0) {
                 Some(t) => LuaVec2(t.position()).to_lua(l).unwrap(),
                 None => Value::Nil,
             })
         });
 
-        methods.add_method("remove", |_: &Lua, sel: &Self, e: LuaEntity| unsafe {
-            delete_entity(&mut (*sel.w), e.0);
+        methods.add_method("remove", |_: &Lua, sel: &Self, e: LuaEntity| {
+            delete_entity(&mut sel.w.borrow_mut(), e.0);
             Ok(())
         });
+
+        map::add_map_methods(methods);
+        queries::add_query_methods(methods);
     }
 }
 
-struct LuaDraw {
-    w: *mut World,
+struct LuaDraw<'a> {
+    w: &'a WorldCell<'a>,
     col: Color,
 }
 
-unsafe impl Send for LuaDraw {}
-
-impl UserData for LuaDraw {
+impl<'a> UserData for LuaDraw<'a> {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
-        methods.add_method_mut("circle", |_, sel, (pos, size): (LuaVec2, f32)| unsafe {
-            (*sel.w)
+        methods.add_method_mut("circle", |_, sel, (pos, size): (LuaVec2, f32)| {
+            sel.w
+                .borrow()
                 .write_resource::<ImmediateDraw>()
                 .circle(pos.0, size)
                 .color(sel.col);
@@ -89,18 +108,34 @@ fn color(_: &Lua, (r, g, b, a): (f32, f32, f32, f32)) -> mods::mlua::Result<LuaC
     Ok(LuaColor(Color { r, g, b, a }))
 }
 
-pub fn add_egregoria_lua_stdlib(lua: &Lua, w: &mut World) {
-    lua.globals()
-        .set("world", LuaWorld { w: w as *mut World })
-        .unwrap();
-    lua.globals()
-        .set(
+pub fn add_egregoria_lua_stdlib(lua: &Lua) {
+    mods::add_fn(lua, "color", color)
+}
+
+/// Runs `f` with the `world` and `draw` globals borrowing `w`.
+/// They are invalidated when `f` returns, so scripts cannot keep them around.
+pub fn with_world<R>(lua: &Lua, w: &mut World, f: impl FnOnce(&Lua) -> R) -> Option<R> {
+    let cell: WorldCell = RefCell::new(&mut *w);
+    let r = lua.scope(|scope| {
+        let globals = lua.globals();
+        globals.set(
+            "world",
+            scope.create_nonstatic_userdata(LuaWorld { w: &cell })?,
+        )?;
+        globals.set(
             "draw",
-            LuaDraw {
-                w: w as *mut World,
+            scope.create_nonstatic_userdata(LuaDraw {
+                w: &cell,
                 col: Color::WHITE,
-            },
-        )
-        .unwrap();
-    mods::add_fn(lua, "color", color)
+            })?,
+        )?;
+        Ok(f(lua))
+    });
+    match r {
+        Ok(r) => Some(r),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
 }
@@ -0,0 +1,196 @@
+use super::map::{LuaIntersection, LuaLane, LuaRoad};
+use super::{LuaEntity, LuaWorld};
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::{Itinerary, ItineraryKind, Route};
+use crate::pedestrians::PedestrianComponent;
+use crate::physics::{Collider, CollisionWorld, Kinematics};
+use crate::stats::{IncidentKind, SafetyLog};
+use crate::vehicles::{VehicleComponent, VehicleState};
+use geom::Vec2;
+use map_model::{Map, ProjectKind};
+use mods::mlua::{Lua, Result, ToLua, UserDataMethods, Value};
+use mods::LuaVec2;
+use specs::{Component, Join, World, WorldExt};
+use std::collections::HashSet;
+
+/// Entities having the component `T` within `radius` of `pos`. The collision world finds the
+/// colliders nearby, only the entities owning one of them are then looked at.
+fn around<T: Component>(w: &World, pos: Vec2, radius: f32) -> Vec<LuaEntity> {
+    let r2 = radius * radius;
+    let near: HashSet<_> = w
+        .read_resource::<CollisionWorld>()
+        .query_around(pos, radius)
+        .filter(|&(_, p)| Vec2::from(p).distance2(pos) <= r2)
+        .map(|(h, _)| h)
+        .collect();
+    if near.is_empty() {
+        return vec![];
+    }
+
+    (
+        &w.entities(),
+        &w.read_storage::<Collider>(),
+        &w.read_storage::<T>(),
+    )
+        .join()
+        .filter(|(_, coll, _)| near.contains(&coll.0))
+        .map(|(e, _, _)| LuaEntity(e))
+        .collect()
+}
+
+fn state_name(state: &VehicleState) -> &'static str {
+    match state {
+        VehicleState::Parked(_) => "parked",
+        VehicleState::ParkedToRoad => "unparking",
+        VehicleState::Driving => "driving",
+        VehicleState::RoadToPark(_, _) => "parking",
+    }
+}
+
+fn itinerary_table<'lua>(l: &'lua Lua, it: &Itinerary, time: f64) -> Result<Value<'lua>> {
+    let t = l.create_table()?;
+    let (kind, steps) = match it.kind() {
+        ItineraryKind::None => ("none", 0),
+        ItineraryKind::WaitUntil(_) => ("wait", 0),
+        ItineraryKind::Simple => ("simple", 0),
+        ItineraryKind::Route(Route { reversed_route, .. }) => ("route", reversed_route.len()),
+    };
+    t.set("kind", kind)?;
+    // Points left on the current lane or turn, and lanes or turns left after it
+    t.set("remaining_points", it.remaining_points())?;
+    t.set("remaining_steps", steps)?;
+    t.set("terminal", it.is_terminal())?;
+    t.set("ended", it.has_ended(time))?;
+    if let Some(p) = it.get_point() {
+        t.set("objective", LuaVec2(p))?;
+    }
+    if let Some(p) = it.get_terminal() {
+        t.set("destination", LuaVec2(p))?;
+    }
+    t.to_lua(l)
+}
+
+/// What lies under `pos`: an intersection, a lane of a road, a building or the ground
+fn map_at<'lua>(l: &'lua Lua, map: &Map, pos: Vec2) -> Result<Value<'lua>> {
+    let t = l.create_table()?;
+    match map.project(pos).kind {
+        ProjectKind::Inter(id) => {
+            t.set("kind", "intersection")?;
+            t.set("intersection", LuaIntersection(id))?;
+        }
+        ProjectKind::Road(id) => {
+            t.set("kind", "lane")?;
+            t.set("road", LuaRoad(id))?;
+
+            let lane = map.roads()[id]
+                .lanes_iter()
+                .filter_map(|(id, _)| map.lanes().get(id))
+                .min_by(|a, b| {
+                    a.points
+                        .project_dist2(pos)
+                        .partial_cmp(&b.points.project_dist2(pos))
+                        .unwrap()
+                });
+            if let Some(lane) = lane {
+                t.set("lane", LuaLane(lane.id))?;
+                t.set("lane_kind", format!("{:?}", lane.kind).to_lowercase())?;
+            }
+        }
+        ProjectKind::House(id) => {
+            t.set("kind", "house")?;
+            if let Some(h) = map.houses().get(id) {
+                t.set("building_kind", h.kind.name())?;
+                t.set("floors", h.floors)?;
+                t.set("entrance", LuaVec2(h.entrance))?;
+            }
+        }
+        ProjectKind::Ground => t.set("kind", "ground")?,
+    }
+    t.to_lua(l)
+}
+
+/// Read access to the agents, the time and the map for scenario conditions
+pub(super) fn add_query_methods<'lua, 'a, M: UserDataMethods<'lua, LuaWorld<'a>>>(methods: &mut M) {
+    methods.add_method("speed", |l: &Lua, sel: &LuaWorld, e: LuaEntity| {
+        Ok(match sel.w.borrow().read_storage::<Kinematics>().get(e.0) {
+            Some(k) => k.velocity.magnitude().to_lua(l)?,
+            None => Value::Nil,
+        })
+    });
+
+    methods.add_method("vehicle_state", |l: &Lua, sel: &LuaWorld, e: LuaEntity| {
+        Ok(
+            match sel.w.borrow().read_storage::<VehicleComponent>().get(e.0) {
+                Some(v) => state_name(&v.state).to_lua(l)?,
+                None => Value::Nil,
+            },
+        )
+    });
+
+    methods.add_method("wait_time", |l: &Lua, sel: &LuaWorld, e: LuaEntity| {
+        Ok(
+            match sel.w.borrow().read_storage::<VehicleComponent>().get(e.0) {
+                Some(v) => v.wait_time.to_lua(l)?,
+                None => Value::Nil,
+            },
+        )
+    });
+
+    methods.add_method("itinerary", |l: &Lua, sel: &LuaWorld, e: LuaEntity| {
+        let w = sel.w.borrow();
+        let time = w.read_resource::<TimeInfo>().time;
+        match w.read_storage::<Itinerary>().get(e.0) {
+            Some(it) => itinerary_table(l, it, time),
+            None => Ok(Value::Nil),
+        }
+    });
+
+    methods.add_method(
+        "vehicles_around",
+        |_: &Lua, sel: &LuaWorld, (pos, radius): (LuaVec2, f32)| {
+            Ok(around::<VehicleComponent>(&sel.w.borrow(), pos.0, radius))
+        },
+    );
+
+    methods.add_method(
+        "pedestrians_around",
+        |_: &Lua, sel: &LuaWorld, (pos, radius): (LuaVec2, f32)| {
+            Ok(around::<PedestrianComponent>(
+                &sel.w.borrow(),
+                pos.0,
+                radius,
+            ))
+        },
+    );
+
+    methods.add_method("time", |_: &Lua, sel: &LuaWorld, (): ()| {
+        Ok(sel.w.borrow().read_resource::<TimeInfo>().time)
+    });
+
+    methods.add_method("set_time_speed", |_: &Lua, sel: &LuaWorld, speed: f32| {
+        sel.w.borrow().write_resource::<TimeInfo>().time_speed = speed.max(0.0);
+        Ok(())
+    });
+
+    methods.add_method("map_at", |l: &Lua, sel: &LuaWorld, pos: LuaVec2| {
+        map_at(l, &sel.w.borrow().read_resource::<Map>(), pos.0)
+    });
+
+    methods.add_method(
+        "collisions",
+        |_: &Lua, sel: &LuaWorld, since: Option<f64>| {
+            let w = sel.w.borrow();
+            let log = w.read_resource::<SafetyLog>();
+            Ok(log.count_since(IncidentKind::Collision, since.unwrap_or(log.scenario_start)))
+        },
+    );
+
+    methods.add_method(
+        "near_misses",
+        |_: &Lua, sel: &LuaWorld, since: Option<f64>| {
+            let w = sel.w.borrow();
+            let log = w.read_resource::<SafetyLog>();
+            Ok(log.count_since(IncidentKind::NearMiss, since.unwrap_or(log.scenario_start)))
+        },
+    );
+}
@@ -0,0 +1,102 @@
+use crate::pedestrians::PedestrianComponent;
+use crate::saveload;
+use crate::utils::delete_entity;
+use crate::vehicles::VehicleComponent;
+use geom::Vec2;
+use map_model::{add_doublecircle, add_grid, load_parismap, load_testfield, Map};
+use mods::mlua::{Function, Lua, Table};
+use mods::LuaVec2;
+use specs::{Join, World, WorldExt};
+
+/// Map described by the `Map` table of a scenario, without the `build` step
+fn map_from_decl(decl: &Table) -> Result<Map, String> {
+    let err = |e: mods::mlua::Error| e.to_string();
+
+    let mut map = match decl.get::<_, Option<String>>("file").map_err(err)? {
+        Some(path) => saveload::load_map_file(&path)?
+            .ok_or_else(|| format!("could not find the map {}", path))?
+            .into(),
+        None => Map::empty(),
+    };
+
+    if let Some(generator) = decl.get::<_, Option<String>>("generator").map_err(err)? {
+        let pos = decl
+            .get::<_, Option<LuaVec2>>("pos")
+            .map_err(err)?
+            .map_or(Vec2::ZERO, |p| p.0);
+        match generator.as_str() {
+            "testfield" => load_testfield(&mut map),
+            "grid" => {
+                let size = decl
+                    .get::<_, Option<usize>>("size")
+                    .map_err(err)?
+                    .unwrap_or(10);
+                add_grid(pos, &mut map, size)
+            }
+            "doublecircle" => add_doublecircle(pos, &mut map),
+            "paris" => load_parismap(&mut map),
+            x => return Err(format!("unknown map generator {}", x)),
+        }
+    }
+
+    Ok(map)
+}
+
+/// Vehicles and pedestrians refer to the lanes of the map they were made on
+fn remove_agents(world: &mut World) {
+    let mut agents: Vec<_> = (&world.entities(), &world.read_storage::<VehicleComponent>())
+        .join()
+        .map(|(e, _)| e)
+        .collect();
+    agents.extend(
+        (
+            &world.entities(),
+            &world.read_storage::<PedestrianComponent>(),
+        )
+            .join()
+            .map(|(e, _)| e),
+    );
+    for e in agents {
+        delete_entity(world, e);
+    }
+}
+
+/// Replaces the map with the one declared by the scenario's `Map` global, if it has one.
+/// `Map` can have a `file` saved with the game, a `generator` ("testfield", "grid" with `pos`
+/// and `size`, "doublecircle" with `pos`, or "paris") and a `build` function called with the
+/// `world` global once the rest is loaded, to make the layout in Lua.
+/// The vehicles and pedestrians are removed when the map is replaced.
+/// Returns None if the declaration is invalid, in which case the scenario shouldn't start.
+pub fn load_scenario_map(l: &Lua, world: &mut World) -> Option<()> {
+    let decl = match l.globals().get::<_, Option<Table>>("Map") {
+        Ok(Some(decl)) => decl,
+        Ok(None) => return Some(()),
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+
+    let map = match map_from_decl(&decl) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("invalid scenario map: {}", e);
+            return None;
+        }
+    };
+
+    remove_agents(world);
+    *world.write_resource::<Map>() = map;
+
+    let build = match decl.get::<_, Option<Function>>("build") {
+        Ok(build) => build,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+    match build {
+        Some(build) => super::with_world(l, world, |_| mods::call_function(l, &build, ()))?,
+        None => Some(()),
+    }
+}
@@ -1,3 +1,7 @@
+use super::LuaCallbacks;
+use crate::engine_interaction::TimeInfo;
+use crate::rand_provider::RandProvider;
+use crate::stats::SafetyLog;
 use mods::mlua::Lua;
 use specs::prelude::*;
 use std::sync::Mutex;
@@ -5,46 +9,68 @@ use std::sync::Mutex;
 #[derive(Default)]
 pub struct RunningScenario {
     pub l: Option<Mutex<Lua>>,
+    callbacks: Option<LuaCallbacks>,
 }
 
-pub struct RunningScenarioSystem;
-impl<'a> System<'a> for RunningScenarioSystem {
-    type SystemData = Write<'a, RunningScenario>;
-
-    fn run(&mut self, mut scenario: Self::SystemData) {
-        if let Some(l) = &scenario.l {
-            let l = l.lock().unwrap();
-            mods::eval_f(&l, "Draw");
-
-            let r: Option<bool> = mods::call_f(&l, "Success");
-            let is_success = match r {
-                Some(x) => x,
-                None => {
-                    drop(l);
-
-                    scenario.l.take();
-                    return;
-                }
-            };
-            if is_success {
-                info!("scenario success");
-                mods::eval_f(&l, "Cleanup");
-
-                drop(l);
-                scenario.l.take();
-            }
+/// Runs a frame of the current scenario. It needs the whole world for the scripts,
+/// so it is called after the dispatcher instead of being a system.
+pub fn run_scenario(world: &mut World) {
+    let mut scenario = std::mem::take(&mut *world.write_resource::<RunningScenario>());
+    let l = match scenario.l.take() {
+        Some(l) => l.into_inner().unwrap(),
+        None => return,
+    };
+
+    if let Some(callbacks) = &mut scenario.callbacks {
+        callbacks.fire(&l, world);
+    }
+
+    let r: Option<bool> = super::with_world(&l, world, |l| {
+        mods::eval_f(l, "Draw");
+        mods::call_f(l, "Success")
+    })
+    .flatten();
+
+    match r {
+        Some(true) => {
+            info!("scenario success");
+            super::with_world(&l, world, |l| mods::eval_f(l, "Cleanup"));
+        }
+        Some(false) => {
+            scenario.l = Some(Mutex::new(l));
+            *world.write_resource::<RunningScenario>() = scenario;
         }
+        None => {}
     }
 }
 
 pub fn set_scenario(world: &mut World, name: &str) {
     if let Some(l) = mods::load(name) {
-        super::add_egregoria_lua_stdlib(&l, world);
-        mods::eval_f(&l, "Init");
-        world
-            .write_resource::<RunningScenario>()
-            .l
-            .replace(Mutex::new(l))
-            .map(|old| mods::eval_f(&old.lock().unwrap(), "Cleanup"));
+        let old = std::mem::take(&mut *world.write_resource::<RunningScenario>());
+        if let Some(old) = old.l {
+            super::with_world(&old.into_inner().unwrap(), world, |l| {
+                mods::eval_f(l, "Cleanup")
+            });
+        }
+
+        super::add_egregoria_lua_stdlib(&l);
+        if super::load_scenario_map(&l, world).is_none() {
+            return;
+        }
+        init_scenario(&l, world);
+        let callbacks = LuaCallbacks::new(world);
+
+        let mut scenario = world.write_resource::<RunningScenario>();
+        scenario.l = Some(Mutex::new(l));
+        scenario.callbacks = Some(callbacks);
     }
 }
+
+/// Calls the scenario's `Init`, incidents before it don't count for the scenario.
+/// The scenario's `rand_in` is seeded from the world's `RandProvider`.
+pub fn init_scenario(l: &Lua, world: &mut World) {
+    let now = world.read_resource::<TimeInfo>().time;
+    world.write_resource::<SafetyLog>().scenario_start = now;
+    mods::seed_rand(l, world.write_resource::<RandProvider>().random());
+    super::with_world(l, world, |l| mods::eval_f(l, "Init"));
+}
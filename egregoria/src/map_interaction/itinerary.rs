@@ -3,7 +3,7 @@ use crate::gui::InspectVec;
 use crate::physics::Transform;
 use geom::Vec2;
 use imgui_inspect_derive::*;
-use map_model::{LaneID, Map, Pathfinder, Traversable, TraverseKind};
+use map_model::{LaneID, Map, Pathfinder, Traversable, TraverseDirection, TraverseKind, TurnID};
 use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use specs::Component;
@@ -90,14 +90,50 @@ impl Itinerary {
             }
         }
 
-        let mut points = points.into_vec();
-        points.drain(..segid - 1);
-
         let mut reversed_route: Vec<Traversable> =
             pather.path(map, cur, l_obj)?.into_iter().rev().collect();
 
         reversed_route.pop(); // Remove start
 
+        Some(Self::follow(pos, cur, reversed_route, obj, map))
+    }
+
+    /// Like `route`, but leaving the lane `cur` through the turn `via`
+    pub fn route_via(
+        pos: Vec2,
+        cur: Traversable,
+        via: TurnID,
+        (l_obj, obj): (LaneID, Vec2),
+        map: &Map,
+        pather: &impl Pathfinder,
+    ) -> Option<Itinerary> {
+        let exit = Traversable::new(TraverseKind::Lane(via.dst), TraverseDirection::Forward);
+        let mut reversed_route: Vec<Traversable> = if via.dst == l_obj {
+            vec![exit]
+        } else {
+            pather.path(map, exit, l_obj)?.into_iter().rev().collect()
+        };
+        reversed_route.push(Traversable::new(
+            TraverseKind::Turn(via),
+            TraverseDirection::Forward,
+        ));
+
+        Some(Self::follow(pos, cur, reversed_route, obj, map))
+    }
+
+    /// Follows `cur` from the projection of `pos`, then the traversables of `reversed_route`
+    fn follow(
+        pos: Vec2,
+        cur: Traversable,
+        reversed_route: Vec<Traversable>,
+        obj: Vec2,
+        map: &Map,
+    ) -> Itinerary {
+        let points = cur.points(map);
+        let (_, segid) = points.project_segment(pos);
+        let mut points = points.into_vec();
+        points.drain(..segid - 1);
+
         let kind = ItineraryKind::Route(Route {
             reversed_route,
             end_pos: obj,
@@ -109,7 +145,7 @@ impl Itinerary {
             local_path: points,
         };
         it.advance(map);
-        Some(it)
+        it
     }
 
     pub fn advance(&mut self, map: &Map) -> Option<Vec2> {
@@ -154,7 +190,12 @@ impl Itinerary {
 
             if dist < OBJECTIVE_OK_DIST * OBJECTIVE_OK_DIST {
                 let k = self.get_travers().unwrap(); // Unwrap ok: We just called check_validity and get_point
-                if self.remaining_points() > 1 || k.can_pass(time.time_seconds, map.lanes()) {
+                if self.remaining_points() > 1
+                    || (k.can_pass(time.time_seconds, map.lanes())
+                        && self
+                            .peek_next()
+                            .map_or(true, |next| next.can_enter(time.time_seconds, map)))
+                {
                     self.advance(map);
                 }
             }
@@ -201,6 +242,14 @@ impl Itinerary {
         }
     }
 
+    /// Traversable following the current one
+    pub fn peek_next(&self) -> Option<&Traversable> {
+        match &self.kind {
+            ItineraryKind::Route(Route { reversed_route, .. }) => reversed_route.last(),
+            _ => None,
+        }
+    }
+
     pub fn kind(&self) -> &ItineraryKind {
         &self.kind
     }
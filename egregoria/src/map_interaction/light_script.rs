@@ -0,0 +1,87 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::Itinerary;
+use crate::physics::Kinematics;
+use crate::vehicles::{VehicleComponent, VehicleState};
+use map_model::{light_script, LaneID, LightPolicy, Map, TraverseKind};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// Vehicles slower than this count as waiting, in m/s
+const STOPPED_SPEED: f32 = 0.5;
+
+/// Calls the `tick` function of the intersections using `LightPolicy::Custom`,
+/// once per simulated second
+#[derive(Default)]
+pub struct LightScriptSystem {
+    last_seconds: Option<u64>,
+}
+
+#[derive(SystemData)]
+pub struct LightScriptSystemData<'a> {
+    map: Write<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+    itinerarys: ReadStorage<'a, Itinerary>,
+}
+
+impl<'a> System<'a> for LightScriptSystem {
+    type SystemData = LightScriptSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let now = data.time.time_seconds;
+        if self.last_seconds.replace(now) == Some(now) {
+            return;
+        }
+
+        let scripted: Vec<_> = data
+            .map
+            .intersections()
+            .values()
+            .filter_map(|i| match &i.light_policy {
+                LightPolicy::Custom(script) => Some((i.id, script.clone())),
+                _ => None,
+            })
+            .collect();
+        if scripted.is_empty() {
+            return;
+        }
+
+        let mut waiting: HashMap<LaneID, u32> = HashMap::new();
+        for (kin, vehicle, it) in (&data.kinematics, &data.vehicles, &data.itinerarys).join() {
+            if !matches!(vehicle.state, VehicleState::Driving)
+                || kin.velocity.magnitude() > STOPPED_SPEED
+            {
+                continue;
+            }
+            if let Some(TraverseKind::Lane(l)) = it.get_travers().map(|t| t.kind) {
+                *waiting.entry(l).or_default() += 1;
+            }
+        }
+
+        let map = &mut *data.map;
+        map.light_scripts().refresh();
+        for (id, script) in scripted {
+            let controls = {
+                let inter = &map.intersections()[id];
+                let groups = inter.incoming_lane_groups(map.roads());
+                let waiting: Vec<u32> = groups
+                    .iter()
+                    .map(|g| g.iter().filter_map(|l| waiting.get(l)).sum())
+                    .collect();
+                light_script::tick(
+                    map.light_scripts(),
+                    &script,
+                    inter,
+                    &groups,
+                    map.lanes(),
+                    data.time.time,
+                    &waiting,
+                )
+            };
+            if let Some(controls) = controls {
+                map.set_lane_controls(id, controls);
+            }
+        }
+    }
+}
@@ -1,5 +1,7 @@
 mod itinerary;
+mod light_script;
 mod parking;
 
 pub use itinerary::*;
+pub use light_script::*;
 pub use parking::*;
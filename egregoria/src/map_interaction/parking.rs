@@ -3,6 +3,8 @@ use map_model::{LaneID, Map, ParkingSpotID};
 use std::collections::HashSet;
 use std::sync::Mutex;
 
+/// Systems reserving or freeing spots should fetch it with `Write`, so that the dispatcher doesn't
+/// run them at the same time and the spots go to the same vehicles from one run to the next
 #[derive(Default)]
 pub struct ParkingManagement {
     reserved_spots: Mutex<HashSet<ParkingSpotID>>, // todo: use chashmap if it becomes a performance issue
@@ -11,15 +13,22 @@ pub struct ParkingManagement {
 impl ParkingManagement {
     pub fn free(&self, spot: ParkingSpotID) {
         assert!(
-            self.reserved_spots.lock().unwrap().remove(&spot), // Unwrap ok: Mutex lives in the main thread
+            self.reserved_spots.lock().unwrap().remove(&spot), // Unwrap ok: only poisoned if a system panicked
             "spot wasn't reserved"
         );
     }
 
+    /// Reserves the given spot, e.g. for a vehicle loaded from a save.
+    /// Returns false if it was already reserved.
+    pub fn reserve(&self, spot: ParkingSpotID) -> bool {
+        // Unwrap ok: only poisoned if a system panicked
+        self.reserved_spots.lock().unwrap().insert(spot)
+    }
+
     pub fn reserve_near(&self, lane: LaneID, near: Vec2, map: &Map) -> Option<ParkingSpotID> {
         let lane = map.lanes().get(lane)?;
 
-        let mut reserved_spots = self.reserved_spots.lock().unwrap(); // Unwrap ok: Mutex lives in the main thread
+        let mut reserved_spots = self.reserved_spots.lock().unwrap(); // Unwrap ok: only poisoned if a system panicked
         let depth = 3;
 
         let mut potential = vec![lane];
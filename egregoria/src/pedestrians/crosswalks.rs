@@ -0,0 +1,127 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::Itinerary;
+use crate::pedestrians::PedestrianComponent;
+use crate::physics::Transform;
+use geom::segment::Segment;
+use geom::Vec2;
+use map_model::{ConflictKind, Map, Traversable, TraverseKind, Turn, TurnID};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// Distance to the kerb under which a pedestrian allowed to cross is about to step on the crosswalk
+const APPROACH_DIST: f32 = 4.0;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CrosswalkUse {
+    /// Pedestrians walking on the crosswalk
+    pub crossing: u32,
+    /// Pedestrians about to step on it
+    pub approaching: u32,
+}
+
+/// Pedestrians on or about to enter each crosswalk, updated every frame
+#[derive(Default)]
+pub struct CrosswalkOccupancy {
+    uses: HashMap<TurnID, CrosswalkUse>,
+}
+
+impl CrosswalkOccupancy {
+    pub fn get(&self, id: TurnID) -> CrosswalkUse {
+        self.uses.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn is_busy(&self, id: TurnID) -> bool {
+        self.uses.contains_key(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uses.is_empty()
+    }
+
+    /// First point where the path crosses a busy crosswalk conflicting with the turn,
+    /// with its distance from the start of the path
+    pub fn first_busy_crossing(
+        &self,
+        map: &Map,
+        turn: TurnID,
+        path: &[Vec2],
+    ) -> Option<(Vec2, f32)> {
+        let inter = map.intersections().get(turn.parent)?;
+        let busy: Vec<&Turn> = inter
+            .conflicts(turn)
+            .filter(|&(id, kind)| kind == ConflictKind::Crossing && self.is_busy(id))
+            .filter_map(|(id, _)| inter.find_turn(id))
+            .collect();
+        if busy.is_empty() {
+            return None;
+        }
+
+        let mut dist = 0.0;
+        for w in path.windows(2) {
+            let seg = Segment::new(w[0], w[1]);
+            let closest = busy
+                .iter()
+                .filter_map(|t| seg.intersection(&Segment::new(t.points.first(), t.points.last())))
+                .min_by(|a, b| a.distance2(w[0]).partial_cmp(&b.distance2(w[0])).unwrap());
+
+            if let Some(p) = closest {
+                return Some((p, dist + w[0].distance(p)));
+            }
+
+            dist += w[0].distance(w[1]);
+        }
+        None
+    }
+}
+
+fn as_crosswalk<'a>(travers: &Traversable, map: &'a Map) -> Option<&'a Turn> {
+    match travers.kind {
+        TraverseKind::Turn(id) => map
+            .intersections()
+            .get(id.parent)?
+            .find_turn(id)
+            .filter(|t| t.kind.is_crosswalk()),
+        TraverseKind::Lane(_) => None,
+    }
+}
+
+pub struct CrosswalkSystem;
+
+#[derive(SystemData)]
+pub struct CrosswalkSystemData<'a> {
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    occupancy: Write<'a, CrosswalkOccupancy>,
+    transforms: ReadStorage<'a, Transform>,
+    itinerarys: ReadStorage<'a, Itinerary>,
+    pedestrians: ReadStorage<'a, PedestrianComponent>,
+}
+
+impl<'a> System<'a> for CrosswalkSystem {
+    type SystemData = CrosswalkSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let map = &*data.map;
+        let time = data.time.time_seconds;
+        let uses = &mut data.occupancy.uses;
+        uses.clear();
+
+        for (trans, it, _) in (&data.transforms, &data.itinerarys, &data.pedestrians).join() {
+            if let Some(turn) = it.get_travers().and_then(|t| as_crosswalk(t, map)) {
+                uses.entry(turn.id).or_default().crossing += 1;
+                continue;
+            }
+
+            if it.remaining_points() != 1 {
+                continue;
+            }
+            let next = unwrap_or!(it.peek_next(), continue);
+            let turn = unwrap_or!(as_crosswalk(next, map), continue);
+            let kerb = unwrap_or!(it.get_point(), continue);
+
+            if next.can_enter(time, map) && kerb.distance(trans.position()) < APPROACH_DIST {
+                uses.entry(turn.id).or_default().approaching += 1;
+            }
+        }
+    }
+}
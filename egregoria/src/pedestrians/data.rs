@@ -10,7 +10,6 @@ use crate::RandProvider;
 use geom::{vec2, Vec2};
 use imgui_inspect_derive::*;
 use map_model::{LaneKind, Map};
-use rand_distr::Distribution;
 use serde::{Deserialize, Serialize};
 use specs::{Builder, World, WorldExt};
 use specs::{Component, DenseVecStorage};
@@ -49,12 +48,15 @@ pub fn spawn_pedestrian(world: &mut World) {
             ..Default::default()
         },
     );
-    let color = random_pedestrian_shirt_color();
+    let mut rng = world.write_resource::<RandProvider>();
+    let color = random_pedestrian_shirt_color(&mut rng);
+    let pedestrian = PedestrianComponent::new(&mut rng);
+    drop(rng);
 
     world
         .create_entity()
         .with(Transform::new(pos))
-        .with(PedestrianComponent::default())
+        .with(pedestrian)
         .with(Itinerary::none())
         .with(Kinematics::from_mass(80.0))
         .with(Movable)
@@ -94,19 +96,17 @@ pub fn spawn_pedestrian(world: &mut World) {
         .build();
 }
 
-impl Default for PedestrianComponent {
-    fn default() -> Self {
+impl PedestrianComponent {
+    pub fn new(rng: &mut RandProvider) -> Self {
         Self {
-            walking_speed: rand_distr::Normal::new(1.34f32, 0.26) // https://arxiv.org/pdf/cond-mat/9805244.pdf
-                .unwrap() // Unwrap ok: it is a normal distribution
-                .sample(&mut rand::thread_rng())
-                .max(0.5),
+            // https://arxiv.org/pdf/cond-mat/9805244.pdf
+            walking_speed: rng.rand_normal(1.34f32, 0.26).max(0.5),
             walk_anim: 0.0,
         }
     }
 }
 
-pub fn random_pedestrian_shirt_color() -> Color {
+pub fn random_pedestrian_shirt_color(rng: &mut RandProvider) -> Color {
     let car_colors: [(Color, f32); 7] = [
         (Color::from_hex(0xff_ff_ff), 0.1),  // White
         (Color::from_hex(0x66_66_66), 0.1),  // Gray
@@ -119,7 +119,7 @@ pub fn random_pedestrian_shirt_color() -> Color {
 
     let total: f32 = car_colors.iter().map(|x| x.1).sum();
 
-    let r = rand::random::<f32>() * total;
+    let r = rng.random::<f32>() * total;
     let mut partial = 0.0;
     for (col, freq) in &car_colors {
         partial += freq;
@@ -1,8 +1,10 @@
 use specs::World;
 
+mod crosswalks;
 pub mod data;
 pub mod systems;
 
+pub use crosswalks::*;
 pub use data::*;
 pub use systems::*;
 
@@ -1,13 +1,16 @@
 use crate::engine_interaction::TimeInfo;
-use crate::map_interaction::Itinerary;
+use crate::events::SimEvent;
+use crate::map_interaction::{Itinerary, ItineraryKind};
 use crate::pedestrians::PedestrianComponent;
 use crate::physics::{Collider, CollisionWorld, Kinematics, PhysicsObject, Transform};
+use crate::rand_provider::RandProvider;
 use crate::rendering::meshrender_component::MeshRender;
 use crate::utils::Restrict;
 use geom::{angle_lerp, Vec2};
 use map_model::{LaneKind, Map, PedestrianPath, Traversable, TraverseDirection, TraverseKind};
 use specs::prelude::*;
 use specs::shred::PanicHandler;
+use specs::shrev::EventChannel;
 use std::borrow::Borrow;
 
 #[derive(Default)]
@@ -18,6 +21,9 @@ pub struct PedestrianDecisionData<'a> {
     cow: Read<'a, CollisionWorld, PanicHandler>,
     map: Read<'a, Map, PanicHandler>,
     time: Read<'a, TimeInfo>,
+    events: Write<'a, EventChannel<SimEvent>>,
+    rng: Write<'a, RandProvider, PanicHandler>,
+    entities: Entities<'a>,
     colliders: ReadStorage<'a, Collider>,
     itinerarys: WriteStorage<'a, Itinerary>,
     transforms: WriteStorage<'a, Transform>,
@@ -33,7 +39,10 @@ impl<'a> System<'a> for PedestrianDecision {
         let cow: &CollisionWorld = data.cow.borrow();
         let map: &Map = data.map.borrow();
         let time: &TimeInfo = data.time.borrow();
+        let events = &mut *data.events;
+        let rng = &mut *data.rng;
         (
+            &data.entities,
             &data.colliders,
             &mut data.itinerarys,
             &mut data.transforms,
@@ -42,8 +51,13 @@ impl<'a> System<'a> for PedestrianDecision {
             &mut data.mr,
         )
             .join()
-            .for_each(|(coll, it, trans, kin, pedestrian, mr)| {
-                objective_update(it, trans, map, time);
+            .for_each(|(ent, coll, it, trans, kin, pedestrian, mr)| {
+                if it.has_ended(time.time)
+                    && matches!(it.kind(), ItineraryKind::Simple | ItineraryKind::Route(_))
+                {
+                    events.single_write(SimEvent::PedestrianArrived(ent));
+                }
+                objective_update(it, trans, map, time, rng);
 
                 let (_, my_obj) = cow.get(coll.0).expect("Handle not in collision world");
                 let neighbors = cow.query_around(trans.position(), 10.0);
@@ -56,7 +70,7 @@ impl<'a> System<'a> for PedestrianDecision {
                 });
 
                 let (desired_v, desired_dir) =
-                    calc_decision(pedestrian, trans, kin, map, my_obj, it, objs);
+                    calc_decision(pedestrian, trans, kin, map, time, my_obj, it, objs);
 
                 walk_anim(pedestrian, mr, time, kin);
                 physics(kin, trans, time, desired_v, desired_dir);
@@ -110,6 +124,7 @@ pub fn calc_decision<'a>(
     trans: &Transform,
     kin: &Kinematics,
     map: &Map,
+    time: &TimeInfo,
     my_obj: &PhysicsObject,
     it: &Itinerary,
     neighs: impl Iterator<Item = (Vec2, &'a PhysicsObject)>,
@@ -128,7 +143,18 @@ pub fn calc_decision<'a>(
         None => return (Vec2::ZERO, trans.direction()),
     };
 
-    let mut desired_v = dir_to_pos * pedestrian.walking_speed;
+    // Wait at the kerb until the crosswalk shows "walk"
+    let waiting = !it.is_terminal()
+        && it.remaining_points() == 1
+        && it
+            .peek_next()
+            .map_or(false, |next| !next.can_enter(time.time_seconds, map));
+
+    let mut desired_v = if waiting && delta_pos.magnitude2() < 1.0 {
+        Vec2::ZERO
+    } else {
+        dir_to_pos * pedestrian.walking_speed
+    };
 
     for (his_pos, his_obj) in neighs {
         if his_pos == position {
@@ -163,7 +189,13 @@ pub fn calc_decision<'a>(
     (desired_v, desired_dir)
 }
 
-pub fn objective_update(itinerary: &mut Itinerary, trans: &Transform, map: &Map, time: &TimeInfo) {
+pub fn objective_update(
+    itinerary: &mut Itinerary,
+    trans: &Transform,
+    map: &Map,
+    time: &TimeInfo,
+    rng: &mut RandProvider,
+) {
     if itinerary.has_ended(time.time) {
         let mut last_travers = itinerary.get_travers().copied();
         if last_travers.is_none() {
@@ -172,13 +204,18 @@ pub fn objective_update(itinerary: &mut Itinerary, trans: &Transform, map: &Map,
                 .map(|x| Traversable::new(TraverseKind::Lane(x), TraverseDirection::Forward));
         }
 
-        *itinerary = next_objective(trans.position(), map, last_travers.as_ref())
+        *itinerary = next_objective(trans.position(), map, last_travers.as_ref(), rng)
             .unwrap_or_else(|| Itinerary::wait_until(time.time + 10.0));
     }
 }
 
-fn next_objective(pos: Vec2, map: &Map, last_travers: Option<&Traversable>) -> Option<Itinerary> {
-    let l = map.get_random_lane(LaneKind::Walking, &mut rand::thread_rng())?;
+fn next_objective(
+    pos: Vec2,
+    map: &Map,
+    last_travers: Option<&Traversable>,
+    rng: &mut RandProvider,
+) -> Option<Itinerary> {
+    let l = map.get_random_lane(LaneKind::Walking, &mut rng.rng)?;
 
     Itinerary::route(
         pos,
@@ -186,7 +223,7 @@ fn next_objective(pos: Vec2, map: &Map, last_travers: Option<&Traversable>) -> O
         (
             l.id,
             l.points
-                .point_along(rand::random::<f32>() * l.points.length()),
+                .point_along(rng.random::<f32>() * l.points.length()),
         ),
         map,
         &PedestrianPath,
@@ -19,9 +19,19 @@ pub enum PhysicsGroup {
     Unknown,
     Vehicles,
     Pedestrians,
+    EmergencyVehicles,
 }
 
-enum_inspect_impl!(PhysicsGroup; PhysicsGroup::Unknown, PhysicsGroup::Vehicles, PhysicsGroup::Pedestrians);
+enum_inspect_impl!(PhysicsGroup; PhysicsGroup::Unknown, PhysicsGroup::Vehicles, PhysicsGroup::Pedestrians, PhysicsGroup::EmergencyVehicles);
+
+impl PhysicsGroup {
+    pub fn is_vehicle(self) -> bool {
+        matches!(
+            self,
+            PhysicsGroup::Vehicles | PhysicsGroup::EmergencyVehicles
+        )
+    }
+}
 
 #[derive(Clone, Copy, Inspect)]
 pub struct PhysicsObject {
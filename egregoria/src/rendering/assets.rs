@@ -11,7 +11,37 @@ pub struct AssetID {
 
 impl AssetID {
     pub const CAR: AssetID = AssetID { id: 0 };
-    pub const PEDESTRIAN: AssetID = AssetID { id: 1 };
+}
+
+/// Sprites that can be used by `AssetRender`, the renderer loads them as they get registered
+pub struct AssetRegistry {
+    paths: Vec<String>,
+}
+
+impl Default for AssetRegistry {
+    fn default() -> Self {
+        Self {
+            paths: vec!["resources/car.png".to_string()],
+        }
+    }
+}
+
+impl AssetRegistry {
+    pub fn register(&mut self, path: &str) -> AssetID {
+        let id = match self.paths.iter().position(|x| x == path) {
+            Some(id) => id,
+            None => {
+                self.paths.push(path.to_string());
+                self.paths.len() - 1
+            }
+        };
+        AssetID { id: id as u16 }
+    }
+
+    /// Paths of the sprites, indexed by AssetID
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
 }
 
 #[derive(Clone, Copy, Component, Inspect)]
@@ -1,7 +1,11 @@
+use map_model::{SerializedMap, SerializedMapV0, MAP_FORMAT_VERSION};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, ErrorKind};
+
+/// Start of the map files, before the format version. Files without it are from before versioning.
+const MAP_MAGIC: u64 = 0x4547_5245_4d41_5000;
 
 fn filename(name: &'static str) -> String {
     format!("world/{}.bc", name)
@@ -31,13 +35,123 @@ pub fn load_or_default<T: DeserializeOwned + Default>(name: &'static str) -> T {
 }
 
 pub fn load<T: DeserializeOwned>(name: &'static str) -> Option<T> {
-    let file = open_file(&filename(name))?;
+    load_file(&filename(name))
+}
+
+fn load_file<T: DeserializeOwned>(path: &str) -> Option<T> {
+    let file = open_file(path)?;
 
     let des = bincode::deserialize_from(BufReader::new(file));
-    des.map_err(|err| error!("failed deserializing {}: {}", name, err))
+    des.map_err(|err| error!("failed deserializing {}: {}", path, err))
         .map(|x| {
-            info!("successfully loaded {}", name);
+            info!("successfully loaded {}", path);
             x
         })
         .ok()
 }
+
+/// Saves the map along with its format version
+pub fn save_map(map: &SerializedMap, name: &'static str) -> Option<()> {
+    save(&(MAP_MAGIC, MAP_FORMAT_VERSION, map), name)
+}
+
+/// Loads a map saved by `save_map` or from before versioning.
+/// Ok(None) if there is no such file, Err if it exists but can't be read.
+pub fn load_map(name: &'static str) -> Result<Option<SerializedMap>, String> {
+    load_map_file(&filename(name))
+}
+
+/// Same as `load_map`, from any path
+pub fn load_map_file(path: &str) -> Result<Option<SerializedMap>, String> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("could not open {}: {}", path, e)),
+    };
+    let mut reader = BufReader::new(file);
+
+    let map = match bincode::deserialize_from::<_, (u64, u32)>(&mut reader) {
+        Ok((MAP_MAGIC, MAP_FORMAT_VERSION)) => bincode::deserialize_from(reader),
+        Ok((MAP_MAGIC, version)) => {
+            return Err(format!(
+                "{} has the map format version {}, only {} is supported",
+                path, version, MAP_FORMAT_VERSION
+            ))
+        }
+        _ => {
+            info!("{} is from before map versioning, migrating it", path);
+            let file = File::open(path).map_err(|e| e.to_string())?;
+            bincode::deserialize_from::<_, SerializedMapV0>(BufReader::new(file)).map(Into::into)
+        }
+    };
+
+    map.map(|m| {
+        info!("successfully loaded {}", path);
+        Some(m)
+    })
+    .map_err(|err| format!("failed deserializing {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::load_map_file;
+    use geom::polygon::Polygon;
+    use geom::vec2;
+    use map_model::{add_grid, BuildingKind, HouseID, Map};
+    use serde::Serialize;
+    use slotmap::DenseSlotMap;
+    use std::fs::File;
+    use std::io::BufWriter;
+
+    #[derive(Serialize)]
+    struct HouseV0 {
+        id: HouseID,
+        exterior: Polygon,
+    }
+
+    #[test]
+    fn loads_map_from_before_versioning() {
+        let mut map = Map::empty();
+        add_grid(vec2(0.0, 0.0), &mut map, 2);
+
+        let mut houses: DenseSlotMap<HouseID, HouseV0> = DenseSlotMap::with_key();
+        houses.insert_with_key(|id| HouseV0 {
+            id,
+            exterior: Polygon::rect(10.0, 10.0),
+        });
+
+        // Same layout as the unversioned SerializedMap
+        let path = std::env::temp_dir().join("egregoria_map_v0.bc");
+        let file = File::create(&path).unwrap();
+        bincode::serialize_into(
+            BufWriter::new(file),
+            &(
+                map.roads(),
+                map.intersections(),
+                &houses,
+                map.lanes(),
+                &map.parking,
+            ),
+        )
+        .unwrap();
+
+        let loaded: Map = load_map_file(path.to_str().unwrap())
+            .unwrap()
+            .expect("the file was just written")
+            .into();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(loaded.roads().len(), map.roads().len());
+        assert_eq!(loaded.lanes().len(), map.lanes().len());
+        assert_eq!(loaded.houses().len(), 1);
+        let house = loaded.houses().values().next().unwrap();
+        assert_eq!(house.kind, BuildingKind::Residential);
+        assert_eq!(house.floors, 1);
+    }
+
+    #[test]
+    fn missing_map_is_not_an_error() {
+        let path = std::env::temp_dir().join("egregoria_no_such_map.bc");
+        assert!(load_map_file(path.to_str().unwrap()).unwrap().is_none());
+    }
+}
@@ -0,0 +1,321 @@
+use crate::vehicles::GridlockPolicy;
+use geom::Vec2;
+use map_model::{IntersectionID, LaneID};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Speed under which a vehicle is considered to be queuing, in m/s
+pub const QUEUE_SPEED: f32 = 1.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct LaneStats {
+    pub start: f64,
+    pub end: f64,
+    pub lane: LaneID,
+    /// Vehicles leaving the lane, in vehicles per hour
+    pub flow: f32,
+    /// Time-weighted mean speed of the vehicles on the lane, in m/s
+    pub mean_speed: f32,
+    /// Mean fraction of the lane length covered by vehicles
+    pub occupancy: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct IntersectionStats {
+    pub start: f64,
+    pub end: f64,
+    pub inter: IntersectionID,
+    pub pos: Vec2,
+    /// Number of vehicles that went through the intersection
+    pub throughput: u32,
+    /// Time lost compared to cruising speed by the approaching vehicles, in vehicle-seconds
+    pub total_delay: f32,
+    /// Total delay divided by the throughput
+    pub mean_delay: f32,
+    /// Mean number of stopped vehicles on the approaches
+    pub mean_queue: f32,
+    pub max_queue: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TripStats {
+    pub start: f64,
+    pub end: f64,
+    pub distance: f32,
+}
+
+impl TripStats {
+    pub fn travel_time(&self) -> f32 {
+        (self.end - self.start) as f32
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GridlockEvent {
+    pub time: f64,
+    pub pos: Vec2,
+    pub n_vehicles: u32,
+    /// Policy applied to resolve the gridlock
+    pub policy: GridlockPolicy,
+}
+
+#[derive(Default)]
+struct LaneAcc {
+    exits: u32,
+    vehicle_time: f32,
+    speed_time: f32,
+    occupied_time: f32,
+}
+
+#[derive(Default)]
+struct IntersectionAcc {
+    pos: Vec2,
+    throughput: u32,
+    delay: f32,
+    queue_time: f32,
+    max_queue: u32,
+}
+
+/// Traffic measurements aggregated over fixed intervals of simulated time.
+/// Filled by the `TrafficStatsSystem`.
+pub struct TrafficStats {
+    /// Length of an aggregation interval, in seconds of simulated time
+    pub interval: f32,
+    pub lanes: Vec<LaneStats>,
+    pub intersections: Vec<IntersectionStats>,
+    pub trips: Vec<TripStats>,
+    pub gridlocks: Vec<GridlockEvent>,
+
+    interval_start: Option<f64>,
+    /// Ordered so that the exported rows come in the same order every run
+    cur_lanes: BTreeMap<LaneID, LaneAcc>,
+    cur_inters: BTreeMap<IntersectionID, IntersectionAcc>,
+}
+
+impl Default for TrafficStats {
+    fn default() -> Self {
+        Self {
+            interval: 60.0,
+            lanes: vec![],
+            intersections: vec![],
+            trips: vec![],
+            gridlocks: vec![],
+            interval_start: None,
+            cur_lanes: Default::default(),
+            cur_inters: Default::default(),
+        }
+    }
+}
+
+impl TrafficStats {
+    /// Start of the interval currently being measured
+    pub fn interval_start(&self) -> Option<f64> {
+        self.interval_start
+    }
+
+    /// Start and end of the last completed interval
+    pub fn last_interval(&self) -> Option<(f64, f64)> {
+        self.lanes
+            .iter()
+            .map(|x| (x.start, x.end))
+            .chain(self.intersections.iter().map(|x| (x.start, x.end)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+    }
+
+    pub fn last_lanes(&self) -> impl Iterator<Item = &LaneStats> {
+        let end = self.last_interval().map(|x| x.1);
+        self.lanes.iter().filter(move |x| Some(x.end) == end)
+    }
+
+    pub fn last_intersections(&self) -> impl Iterator<Item = &IntersectionStats> {
+        let end = self.last_interval().map(|x| x.1);
+        self.intersections
+            .iter()
+            .filter(move |x| Some(x.end) == end)
+    }
+
+    pub fn clear(&mut self) {
+        self.lanes.clear();
+        self.intersections.clear();
+        self.trips.clear();
+        self.gridlocks.clear();
+        self.interval_start = None;
+        self.cur_lanes.clear();
+        self.cur_inters.clear();
+    }
+
+    pub(crate) fn begin(&mut self, time: f64) {
+        if self.interval_start.is_none() {
+            self.interval_start = Some(time);
+        }
+    }
+
+    pub(crate) fn lane_sample(&mut self, lane: LaneID, speed: f32, occupied: f32, dt: f32) {
+        let acc = self.cur_lanes.entry(lane).or_default();
+        acc.vehicle_time += dt;
+        acc.speed_time += speed * dt;
+        acc.occupied_time += occupied * dt;
+    }
+
+    pub(crate) fn lane_exit(&mut self, lane: LaneID) {
+        self.cur_lanes.entry(lane).or_default().exits += 1;
+    }
+
+    pub(crate) fn intersection_delay(&mut self, inter: IntersectionID, pos: Vec2, delay: f32) {
+        let acc = self.cur_inters.entry(inter).or_default();
+        acc.pos = pos;
+        acc.delay += delay;
+    }
+
+    pub(crate) fn intersection_queue(
+        &mut self,
+        inter: IntersectionID,
+        pos: Vec2,
+        queue: u32,
+        dt: f32,
+    ) {
+        let acc = self.cur_inters.entry(inter).or_default();
+        acc.pos = pos;
+        acc.queue_time += queue as f32 * dt;
+        acc.max_queue = acc.max_queue.max(queue);
+    }
+
+    pub(crate) fn intersection_pass(&mut self, inter: IntersectionID, pos: Vec2) {
+        let acc = self.cur_inters.entry(inter).or_default();
+        acc.pos = pos;
+        acc.throughput += 1;
+    }
+
+    pub(crate) fn trip(&mut self, trip: TripStats) {
+        self.trips.push(trip);
+    }
+
+    pub(crate) fn gridlock(&mut self, event: GridlockEvent) {
+        self.gridlocks.push(event);
+    }
+
+    /// Closes the current interval if it lasted long enough
+    pub(crate) fn maybe_finish_interval(&mut self, time: f64) {
+        if let Some(start) = self.interval_start {
+            if time - start >= self.interval as f64 {
+                self.finish_interval(time);
+            }
+        }
+    }
+
+    /// Closes the current interval at the given time, storing its measurements
+    pub fn finish_interval(&mut self, end: f64) {
+        let start = unwrap_or!(self.interval_start, return);
+        let duration = (end - start) as f32;
+        if duration <= 0.0 {
+            return;
+        }
+
+        for (lane, acc) in std::mem::take(&mut self.cur_lanes) {
+            self.lanes.push(LaneStats {
+                start,
+                end,
+                lane,
+                flow: acc.exits as f32 * 3600.0 / duration,
+                mean_speed: if acc.vehicle_time > 0.0 {
+                    acc.speed_time / acc.vehicle_time
+                } else {
+                    0.0
+                },
+                occupancy: acc.occupied_time / duration,
+            });
+        }
+
+        for (inter, acc) in std::mem::take(&mut self.cur_inters) {
+            self.intersections.push(IntersectionStats {
+                start,
+                end,
+                inter,
+                pos: acc.pos,
+                throughput: acc.throughput,
+                total_delay: acc.delay,
+                mean_delay: acc.delay / acc.throughput.max(1) as f32,
+                mean_queue: acc.queue_time / duration,
+                max_queue: acc.max_queue,
+            });
+        }
+
+        self.interval_start = Some(end);
+    }
+
+    /// Writes lanes.csv, intersections.csv, trips.csv and gridlocks.csv in the given directory
+    pub fn export_csv(&self, dir: impl AsRef<Path>) -> std::io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut w = BufWriter::new(File::create(dir.join("lanes.csv"))?);
+        writeln!(w, "start,end,lane,flow_vph,mean_speed,occupancy")?;
+        for l in &self.lanes {
+            writeln!(
+                w,
+                "{:.2},{:.2},{},{:.1},{:.3},{:.4}",
+                l.start,
+                l.end,
+                l.lane.as_ffi(),
+                l.flow,
+                l.mean_speed,
+                l.occupancy
+            )?;
+        }
+        w.flush()?;
+
+        let mut w = BufWriter::new(File::create(dir.join("intersections.csv"))?);
+        writeln!(
+            w,
+            "start,end,intersection,x,y,throughput,total_delay,mean_delay,mean_queue,max_queue"
+        )?;
+        for i in &self.intersections {
+            writeln!(
+                w,
+                "{:.2},{:.2},{},{:.1},{:.1},{},{:.2},{:.2},{:.3},{}",
+                i.start,
+                i.end,
+                i.inter.as_ffi(),
+                i.pos.x,
+                i.pos.y,
+                i.throughput,
+                i.total_delay,
+                i.mean_delay,
+                i.mean_queue,
+                i.max_queue
+            )?;
+        }
+        w.flush()?;
+
+        let mut w = BufWriter::new(File::create(dir.join("trips.csv"))?);
+        writeln!(w, "start,end,travel_time,distance")?;
+        for t in &self.trips {
+            writeln!(
+                w,
+                "{:.2},{:.2},{:.2},{:.1}",
+                t.start,
+                t.end,
+                t.travel_time(),
+                t.distance
+            )?;
+        }
+        w.flush()?;
+
+        let mut w = BufWriter::new(File::create(dir.join("gridlocks.csv"))?);
+        writeln!(w, "time,x,y,n_vehicles,policy")?;
+        for g in &self.gridlocks {
+            writeln!(
+                w,
+                "{:.2},{:.1},{:.1},{},{}",
+                g.time,
+                g.pos.x,
+                g.pos.y,
+                g.n_vehicles,
+                g.policy.as_ref()
+            )?;
+        }
+        w.flush()
+    }
+}
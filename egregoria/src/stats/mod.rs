@@ -0,0 +1,7 @@
+mod data;
+mod safety;
+mod systems;
+
+pub use data::*;
+pub use safety::*;
+pub use systems::*;
@@ -0,0 +1,289 @@
+use crate::engine_interaction::TimeInfo;
+use crate::physics::{Collider, CollisionWorld, Kinematics, Transform};
+use crate::vehicles::{VehicleComponent, VehicleTypes};
+use geom::obb::OBB;
+use geom::Vec2;
+use specs::prelude::*;
+use specs::shred::PanicHandler;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Half of the side of a pedestrian's box, in meters
+const PEDESTRIAN_HALF_SIZE: f32 = 0.25;
+
+/// Step of the time-to-collision search, in seconds
+const TTC_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IncidentKind {
+    /// The boxes of the two agents overlap
+    Collision,
+    /// The two agents would collide within the threshold if they kept their velocities
+    NearMiss,
+}
+
+impl AsRef<str> for IncidentKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            IncidentKind::Collision => "collision",
+            IncidentKind::NearMiss => "near_miss",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Incident {
+    pub time: f64,
+    pub kind: IncidentKind,
+    /// Midpoint of the two agents
+    pub pos: Vec2,
+    pub entities: [Entity; 2],
+    /// In m/s
+    pub speeds: [f32; 2],
+    /// Time to collision in seconds, 0 for collisions
+    pub ttc: f32,
+}
+
+/// Collisions and near-misses between agents, found by the `SafetySystem`.
+/// An incident is logged once, when it starts.
+pub struct SafetyLog {
+    /// Time-to-collision under which a pair of agents is a near-miss, in seconds
+    pub near_miss_ttc: f32,
+    pub incidents: Vec<Incident>,
+    /// Time at which the running scenario was initialized
+    pub scenario_start: f64,
+}
+
+impl Default for SafetyLog {
+    fn default() -> Self {
+        Self {
+            near_miss_ttc: 1.5,
+            incidents: vec![],
+            scenario_start: 0.0,
+        }
+    }
+}
+
+impl SafetyLog {
+    pub fn collisions(&self) -> usize {
+        self.count(IncidentKind::Collision)
+    }
+
+    pub fn near_misses(&self) -> usize {
+        self.count(IncidentKind::NearMiss)
+    }
+
+    fn count(&self, kind: IncidentKind) -> usize {
+        self.incidents.iter().filter(|x| x.kind == kind).count()
+    }
+
+    /// Incidents of the given kind that started after `since`
+    pub fn count_since(&self, kind: IncidentKind, since: f64) -> usize {
+        self.since(since).filter(|x| x.kind == kind).count()
+    }
+
+    /// Incidents that started after `since`
+    pub fn since(&self, since: f64) -> impl Iterator<Item = &Incident> {
+        self.incidents.iter().filter(move |x| x.time >= since)
+    }
+
+    pub fn clear(&mut self) {
+        self.incidents.clear();
+    }
+
+    /// Writes incidents.csv in the given directory
+    pub fn export_csv(&self, dir: impl AsRef<Path>) -> std::io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+
+        let mut w = BufWriter::new(File::create(dir.join("incidents.csv"))?);
+        writeln!(w, "time,kind,x,y,entity_a,entity_b,speed_a,speed_b,ttc")?;
+        for i in &self.incidents {
+            writeln!(
+                w,
+                "{:.2},{},{:.1},{:.1},{},{},{:.2},{:.2},{:.2}",
+                i.time,
+                i.kind.as_ref(),
+                i.pos.x,
+                i.pos.y,
+                i.entities[0].id(),
+                i.entities[1].id(),
+                i.speeds[0],
+                i.speeds[1],
+                i.ttc
+            )?;
+        }
+        w.flush()
+    }
+}
+
+struct Agent {
+    entity: Entity,
+    pos: Vec2,
+    dir: Vec2,
+    velocity: Vec2,
+    /// Half of the length and of the width of the box
+    half_size: (f32, f32),
+    is_vehicle: bool,
+}
+
+impl Agent {
+    fn obb(&self, t: f32) -> OBB {
+        OBB::new(
+            self.pos + self.velocity * t,
+            self.dir,
+            self.half_size.0,
+            self.half_size.1,
+        )
+    }
+
+    fn radius(&self) -> f32 {
+        self.half_size.0.max(self.half_size.1)
+    }
+}
+
+/// First time at which the boxes of the two agents overlap if they keep their velocity
+fn time_to_collision(a: &Agent, b: &Agent, max: f32) -> Option<f32> {
+    let rel_pos = b.pos - a.pos;
+    let rel_vel = b.velocity - a.velocity;
+    if rel_pos.dot(rel_vel) >= 0.0 {
+        return None;
+    }
+
+    let mut t = TTC_STEP;
+    while t <= max {
+        if a.obb(t).intersects(b.obb(t)) {
+            return Some(t);
+        }
+        t += TTC_STEP;
+    }
+    None
+}
+
+/// Checks the boxes of the vehicles and pedestrians every tick.
+/// Pedestrians walking into each other are not incidents.
+#[derive(Default)]
+pub struct SafetySystem {
+    /// Pairs in an incident at the last tick, keyed by (min, max) entity
+    active: HashMap<(Entity, Entity), IncidentKind>,
+}
+
+#[derive(SystemData)]
+pub struct SafetySystemData<'a> {
+    entities: Entities<'a>,
+    time: Read<'a, TimeInfo>,
+    types: Read<'a, VehicleTypes>,
+    coworld: Read<'a, CollisionWorld, PanicHandler>,
+    log: Write<'a, SafetyLog>,
+    transforms: ReadStorage<'a, Transform>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    colliders: ReadStorage<'a, Collider>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+}
+
+impl<'a> System<'a> for SafetySystem {
+    type SystemData = SafetySystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let mut agents = vec![];
+        let mut handles = vec![];
+        let mut by_handle = HashMap::new();
+        for (ent, trans, kin, coll) in (
+            &data.entities,
+            &data.transforms,
+            &data.kinematics,
+            &data.colliders,
+        )
+            .join()
+        {
+            let (half_size, is_vehicle) = match data.vehicles.get(ent) {
+                Some(v) => {
+                    let ty = &data.types[v.kind];
+                    ((ty.width * 0.5, ty.height * 0.5), true)
+                }
+                None => ((PEDESTRIAN_HALF_SIZE, PEDESTRIAN_HALF_SIZE), false),
+            };
+            by_handle.insert(coll.0, agents.len());
+            handles.push(coll.0);
+            agents.push(Agent {
+                entity: ent,
+                pos: trans.position(),
+                dir: trans.direction(),
+                velocity: kin.velocity,
+                half_size,
+                is_vehicle,
+            });
+        }
+
+        let threshold = data.log.near_miss_ttc;
+        let max_radius = agents.iter().map(Agent::radius).fold(0.0, f32::max);
+
+        let mut active = HashMap::new();
+        // In join order, so that incidents are logged in the same order every run
+        for (a, &h) in agents.iter().zip(&handles) {
+            // Agents meeting within the threshold close in by at most twice the speed
+            // of the faster one, so the pair is found from its side
+            let range = a.radius() + max_radius + 2.0 * a.velocity.magnitude() * threshold;
+
+            for (oh, _) in data.coworld.query_around(a.pos, range) {
+                if oh == h {
+                    continue;
+                }
+                let b = &agents[*unwrap_or!(by_handle.get(&oh), continue)];
+                if !a.is_vehicle && !b.is_vehicle {
+                    continue;
+                }
+                let key = if a.entity < b.entity {
+                    (a.entity, b.entity)
+                } else {
+                    (b.entity, a.entity)
+                };
+                if active.contains_key(&key) {
+                    continue;
+                }
+
+                let (kind, ttc) = if a.obb(0.0).intersects(b.obb(0.0)) {
+                    (IncidentKind::Collision, 0.0)
+                } else {
+                    let ttc = unwrap_or!(time_to_collision(a, b, threshold), continue);
+                    (IncidentKind::NearMiss, ttc)
+                };
+                active.insert(key, kind);
+
+                let is_new = match self.active.get(&key) {
+                    Some(IncidentKind::Collision) => false,
+                    Some(IncidentKind::NearMiss) => kind == IncidentKind::Collision,
+                    None => true,
+                };
+                if !is_new {
+                    continue;
+                }
+
+                let (a, b) = if a.entity == key.0 { (a, b) } else { (b, a) };
+                let incident = Incident {
+                    time: data.time.time,
+                    kind,
+                    pos: (a.pos + b.pos) * 0.5,
+                    entities: [a.entity, b.entity],
+                    speeds: [a.velocity.magnitude(), b.velocity.magnitude()],
+                    ttc,
+                };
+                match kind {
+                    IncidentKind::Collision => warn!(
+                        "collision between {:?} and {:?} at {:?}, speeds {:.1}m/s and {:.1}m/s",
+                        a.entity, b.entity, incident.pos, incident.speeds[0], incident.speeds[1]
+                    ),
+                    IncidentKind::NearMiss => info!(
+                        "near-miss between {:?} and {:?} at {:?}, time to collision {:.1}s",
+                        a.entity, b.entity, incident.pos, ttc
+                    ),
+                }
+                data.log.incidents.push(incident);
+            }
+        }
+
+        self.active = active;
+    }
+}
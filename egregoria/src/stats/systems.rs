@@ -0,0 +1,151 @@
+use crate::engine_interaction::TimeInfo;
+use crate::frame_log::FrameLog;
+use crate::map_interaction::Itinerary;
+use crate::physics::Kinematics;
+use crate::stats::{TrafficStats, TripStats, QUEUE_SPEED};
+use crate::vehicles::{VehicleComponent, VehicleState, VehicleTypes};
+use map_model::{IntersectionID, Map, TraverseDirection, TraverseKind};
+use specs::prelude::*;
+use std::collections::HashMap;
+
+struct VehicleTrack {
+    driving: bool,
+    travers: Option<TraverseKind>,
+    /// Start time and distance travelled of the current trip
+    trip: Option<(f64, f32)>,
+}
+
+#[derive(Default)]
+pub struct TrafficStatsSystem {
+    tracks: HashMap<Entity, VehicleTrack>,
+}
+
+#[derive(SystemData)]
+pub struct TrafficStatsSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    types: Read<'a, VehicleTypes>,
+    flog: Read<'a, FrameLog>,
+    stats: Write<'a, TrafficStats>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+    itinerarys: ReadStorage<'a, Itinerary>,
+}
+
+impl<'a> System<'a> for TrafficStatsSystem {
+    type SystemData = TrafficStatsSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        time_it!(data.flog, "Traffic stats");
+
+        let dt = data.time.delta;
+        if dt <= 0.0 {
+            return;
+        }
+
+        let map = &*data.map;
+        let time = data.time.time;
+        let types = &*data.types;
+        let stats = &mut *data.stats;
+
+        stats.begin(time);
+
+        let mut queues: HashMap<IntersectionID, u32> = HashMap::new();
+
+        for (ent, vehicle, kin, it) in (
+            &data.entities,
+            &data.vehicles,
+            &data.kinematics,
+            &data.itinerarys,
+        )
+            .join()
+        {
+            let ty = &types[vehicle.kind];
+            let speed = kin.velocity.magnitude();
+            let driving = matches!(
+                vehicle.state,
+                VehicleState::ParkedToRoad | VehicleState::Driving
+            );
+
+            // Vehicles already driving when first seen are not counted as trips
+            let track = self.tracks.entry(ent).or_insert_with(|| VehicleTrack {
+                driving,
+                travers: None,
+                trip: None,
+            });
+
+            if driving && !track.driving {
+                track.trip = Some((time, 0.0));
+            }
+            if !driving {
+                if let Some((start, distance)) = track.trip.take() {
+                    stats.trip(TripStats {
+                        start,
+                        end: time,
+                        distance,
+                    });
+                }
+            }
+            if let Some((_, ref mut distance)) = track.trip {
+                *distance += speed * dt;
+            }
+            track.driving = driving;
+
+            let travers = if let VehicleState::Driving = vehicle.state {
+                it.get_travers().copied()
+            } else {
+                None
+            };
+
+            if track.travers != travers.map(|x| x.kind) {
+                match track.travers {
+                    Some(TraverseKind::Lane(id)) => stats.lane_exit(id),
+                    Some(TraverseKind::Turn(id)) => {
+                        if let Some(inter) = map.intersections().get(id.parent) {
+                            stats.intersection_pass(id.parent, inter.pos);
+                        }
+                    }
+                    None => {}
+                }
+                track.travers = travers.map(|x| x.kind);
+            }
+
+            let travers = unwrap_or!(travers, continue);
+
+            let delay = dt * (1.0 - speed / ty.cruising_speed).max(0.0);
+
+            let inter = match travers.kind {
+                TraverseKind::Lane(id) => {
+                    let lane = unwrap_or!(map.lanes().get(id), continue);
+                    stats.lane_sample(id, speed, ty.width / lane.length.max(1.0), dt);
+
+                    let inter = match travers.dir {
+                        TraverseDirection::Forward => lane.dst,
+                        TraverseDirection::Backward => lane.src,
+                    };
+                    if speed < QUEUE_SPEED {
+                        *queues.entry(inter).or_default() += 1;
+                    }
+                    inter
+                }
+                TraverseKind::Turn(id) => id.parent,
+            };
+
+            if let Some(i) = map.intersections().get(inter) {
+                stats.intersection_delay(inter, i.pos, delay);
+            }
+        }
+
+        for (inter, queue) in queues {
+            if let Some(i) = map.intersections().get(inter) {
+                stats.intersection_queue(inter, i.pos, queue, dt);
+            }
+        }
+
+        let entities = &data.entities;
+        self.tracks.retain(|&e, _| entities.is_alive(e));
+
+        stats.maybe_finish_interval(time);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::vehicles::VehicleType;
+use serde::{Deserialize, Serialize};
+
+/// The closest obstacle in front of a vehicle
+#[derive(Clone, Copy, Debug)]
+pub struct Leader {
+    /// Bumper to bumper distance, in meters
+    pub gap: f32,
+    /// Speed of the leader along the follower's direction, in m/s
+    pub speed: f32,
+}
+
+/// Law deciding the speed a vehicle should aim for given what's in front of it
+pub trait CarFollowing {
+    /// `desired_speed` is the speed the vehicle would drive at on an empty road.
+    /// `dt` is the time until the next decision.
+    fn target_speed(
+        &self,
+        ty: &VehicleType,
+        speed: f32,
+        desired_speed: f32,
+        leader: Option<Leader>,
+        dt: f32,
+    ) -> f32;
+}
+
+/// Drive at the desired speed unless the leader is closer than the stopping distance
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Rules;
+
+impl CarFollowing for Rules {
+    fn target_speed(
+        &self,
+        ty: &VehicleType,
+        speed: f32,
+        desired_speed: f32,
+        leader: Option<Leader>,
+        _: f32,
+    ) -> f32 {
+        let time_to_stop = speed / ty.deceleration;
+        let stop_dist = time_to_stop * speed * 0.5;
+
+        // Stop at 80 cm of object in front
+        match leader {
+            Some(l) if l.gap < 0.8 + stop_dist => 0.0,
+            _ => desired_speed,
+        }
+    }
+}
+
+/// Intelligent Driver Model, from Treiber, Hennecke and Helbing (2000)
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Idm {
+    /// Desired time headway to the leader, in seconds
+    pub time_headway: f32,
+    /// Gap kept to a stopped leader, in meters
+    pub min_gap: f32,
+    /// Maximum acceleration, in m/s²
+    pub max_acceleration: f32,
+    /// Comfortable deceleration, in m/s²
+    pub comfortable_deceleration: f32,
+    /// Acceleration exponent, usually 4
+    pub delta: f32,
+}
+
+impl Idm {
+    pub const CAR: Idm = Idm {
+        time_headway: 1.5,
+        min_gap: 2.0,
+        max_acceleration: 1.0,
+        comfortable_deceleration: 1.5,
+        delta: 4.0,
+    };
+
+    pub const BUS: Idm = Idm {
+        time_headway: 1.7,
+        min_gap: 2.5,
+        max_acceleration: 0.8,
+        comfortable_deceleration: 1.2,
+        delta: 4.0,
+    };
+
+    pub fn acceleration(&self, speed: f32, desired_speed: f32, leader: Option<Leader>) -> f32 {
+        let v = speed.max(0.0);
+        let free_road = 1.0 - (v / desired_speed.max(0.1)).powf(self.delta);
+
+        let interaction = leader.map_or(0.0, |l| {
+            let approach = v - l.speed;
+            let desired_gap = self.min_gap
+                + (v * self.time_headway
+                    + v * approach
+                        / (2.0 * (self.max_acceleration * self.comfortable_deceleration).sqrt()))
+                .max(0.0);
+            (desired_gap / l.gap.max(0.1)).powi(2)
+        });
+
+        self.max_acceleration * (free_road - interaction)
+    }
+}
+
+impl CarFollowing for Idm {
+    fn target_speed(
+        &self,
+        _: &VehicleType,
+        speed: f32,
+        desired_speed: f32,
+        leader: Option<Leader>,
+        dt: f32,
+    ) -> f32 {
+        (speed + self.acceleration(speed, desired_speed, leader) * dt).max(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum CarFollowingModel {
+    Rules(Rules),
+    Idm(Idm),
+}
+
+impl CarFollowing for CarFollowingModel {
+    fn target_speed(
+        &self,
+        ty: &VehicleType,
+        speed: f32,
+        desired_speed: f32,
+        leader: Option<Leader>,
+        dt: f32,
+    ) -> f32 {
+        match self {
+            CarFollowingModel::Rules(x) => x.target_speed(ty, speed, desired_speed, leader, dt),
+            CarFollowingModel::Idm(x) => x.target_speed(ty, speed, desired_speed, leader, dt),
+        }
+    }
+}
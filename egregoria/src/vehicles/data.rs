@@ -2,12 +2,10 @@ use crate::engine_interaction::TimeInfo;
 use crate::gui::InspectDragf;
 use crate::interaction::Selectable;
 use crate::map_interaction::{Itinerary, ParkingManagement};
-use crate::physics::{
-    Collider, CollisionWorld, Kinematics, PhysicsGroup, PhysicsObject, Transform,
-};
-use crate::rendering::assets::{AssetID, AssetRender};
-use crate::rendering::Color;
+use crate::physics::{Collider, CollisionWorld, Kinematics, PhysicsObject, Transform};
+use crate::rendering::assets::AssetRender;
 use crate::utils::rand_world;
+use crate::vehicles::{VehicleKind, VehicleTypes};
 use crate::RandProvider;
 use geom::splines::Spline;
 use imgui_inspect_derive::*;
@@ -32,16 +30,13 @@ pub enum VehicleState {
 
 debug_inspect_impl!(VehicleState);
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-pub enum VehicleKind {
-    Car,
-    Bus,
-}
-
 #[derive(Component, Debug, Inspect, Serialize, Deserialize)]
 pub struct VehicleComponent {
     #[inspect(proxy_type = "InspectDragf")]
     pub ang_velocity: f32,
+    /// Angle of the front wheels for the bicycle steering model, in radians
+    #[inspect(proxy_type = "InspectDragf")]
+    pub steering_angle: f32,
     #[inspect(proxy_type = "InspectDragf")]
     pub wait_time: f32,
 
@@ -52,60 +47,16 @@ pub struct VehicleComponent {
     pub kind: VehicleKind,
 }
 
-impl VehicleKind {
-    pub fn width(self) -> f32 {
-        match self {
-            VehicleKind::Car => 4.5,
-            VehicleKind::Bus => 9.0,
-        }
-    }
-
-    pub fn height(self) -> f32 {
-        match self {
-            VehicleKind::Car => 2.0,
-            VehicleKind::Bus => 2.0,
-        }
-    }
-
-    pub fn acceleration(self) -> f32 {
-        match self {
-            VehicleKind::Car => 3.0,
-            VehicleKind::Bus => 2.0,
-        }
-    }
-
-    pub fn deceleration(self) -> f32 {
-        match self {
-            VehicleKind::Car => 9.0,
-            VehicleKind::Bus => 9.0,
-        }
-    }
-
-    pub fn min_turning_radius(self) -> f32 {
-        match self {
-            VehicleKind::Car => 3.0,
-            VehicleKind::Bus => 5.0,
-        }
-    }
-
-    pub fn cruising_speed(self) -> f32 {
-        match self {
-            VehicleKind::Car => 15.0,
-            VehicleKind::Bus => 10.0,
-        }
-    }
-
-    pub fn ang_acc(self) -> f32 {
-        match self {
-            VehicleKind::Car => 1.0,
-            VehicleKind::Bus => 0.8,
-        }
-    }
-}
-
 pub fn spawn_parked_vehicle(world: &mut World) {
     let r: f64 = rand_world(world);
 
+    let kind = unwrap_or!(
+        world
+            .read_resource::<VehicleTypes>()
+            .random_kind(&mut world.write_resource::<RandProvider>().rng),
+        return
+    );
+
     let map = world.read_resource::<Map>();
 
     let time = world.read_resource::<TimeInfo>().time;
@@ -123,8 +74,9 @@ pub fn spawn_parked_vehicle(world: &mut World) {
     let spot_id = unwrap_or!(
         pm.reserve_near(
             rl.id,
-            rl.points
-                .point_along(rand::random::<f32>() * rl.points.length()),
+            rl.points.point_along(
+                world.write_resource::<RandProvider>().random::<f32>() * rl.points.length()
+            ),
             &map
         ),
         return
@@ -135,13 +87,7 @@ pub fn spawn_parked_vehicle(world: &mut World) {
     drop(map);
     drop(pm);
 
-    make_vehicle_entity(
-        world,
-        pos,
-        VehicleComponent::new(VehicleKind::Car, spot_id),
-        it,
-        false,
-    );
+    make_vehicle_entity(world, pos, VehicleComponent::new(kind, spot_id), it, false);
 }
 
 pub fn make_vehicle_entity(
@@ -151,18 +97,25 @@ pub fn make_vehicle_entity(
     it: Itinerary,
     mk_collider: bool,
 ) -> Entity {
-    let w = vehicle.kind.width();
+    let types = world.read_resource::<VehicleTypes>();
+    let ty = &types[vehicle.kind];
+    let w = ty.width;
+    let render = AssetRender {
+        id: ty.asset,
+        hide: false,
+        scale: ty.scale,
+        tint: ty.random_color(&mut world.write_resource::<RandProvider>().rng),
+        z: 0.7,
+    };
+    let mass = ty.mass;
+    let group = ty.physics_group();
+    drop(types);
+
     let e = world
         .create_entity()
-        .with(AssetRender {
-            id: AssetID::CAR,
-            hide: false,
-            scale: 4.5,
-            tint: get_random_car_color(),
-            z: 0.7,
-        })
+        .with(render)
         .with(trans)
-        .with(Kinematics::from_mass(1000.0))
+        .with(Kinematics::from_mass(mass))
         .with(Selectable::default())
         .with(vehicle)
         .with(it)
@@ -175,7 +128,7 @@ pub fn make_vehicle_entity(
                 dir: trans.direction(),
                 speed: 0.0,
                 radius: w * 0.5,
-                group: PhysicsGroup::Vehicles,
+                group,
             },
         ));
         world
@@ -187,36 +140,11 @@ pub fn make_vehicle_entity(
     e
 }
 
-pub fn get_random_car_color() -> Color {
-    let car_colors: [(Color, f32); 9] = [
-        (Color::from_hex(0x22_22_22), 0.22),  // Black
-        (Color::from_hex(0xff_ff_ff), 0.19),  // White
-        (Color::from_hex(0x66_66_66), 0.17),  // Gray
-        (Color::from_hex(0xb8_b8_b8), 0.14),  // Silver
-        (Color::from_hex(0x1a_3c_70), 0.1),   // Blue
-        (Color::from_hex(0xd8_22_00), 0.1),   // Red
-        (Color::from_hex(0x7c_4b_24), 0.02),  // Brown
-        (Color::from_hex(0xd4_c6_78), 0.015), // Gold
-        (Color::from_hex(0x72_cb_19), 0.015), // Green
-    ];
-
-    let total: f32 = car_colors.iter().map(|x| x.1).sum();
-
-    let r = rand::random::<f32>() * total;
-    let mut partial = 0.0;
-    for (col, freq) in &car_colors {
-        partial += freq;
-        if partial >= r {
-            return *col;
-        }
-    }
-    unreachable!();
-}
-
 impl VehicleComponent {
     pub fn new(kind: VehicleKind, spot: ParkingSpotID) -> VehicleComponent {
         Self {
             ang_velocity: 0.0,
+            steering_angle: 0.0,
             wait_time: 0.0,
             park_spot: Some(spot),
             state: VehicleState::Parked(spot),
@@ -224,5 +152,3 @@ impl VehicleComponent {
         }
     }
 }
-
-enum_inspect_impl!(VehicleKind; VehicleKind::Car, VehicleKind::Bus);
@@ -0,0 +1,455 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::{Itinerary, ItineraryKind, ParkingManagement};
+use crate::physics::Transform;
+use crate::utils::delete_entity;
+use crate::vehicles::{
+    make_vehicle_entity, VehicleComponent, VehicleKind, VehicleState, VehicleTypes,
+};
+use crate::RandProvider;
+use geom::Vec2;
+use map_model::{
+    DirectionalPath, HouseID, LaneID, LaneKind, Map, Traversable, TraverseDirection, TraverseKind,
+};
+use specs::prelude::*;
+use specs::shred::PanicHandler;
+use specs::{Component, DenseVecStorage};
+
+#[derive(Clone, Copy, Debug)]
+enum DeliveryPhase {
+    /// Parked at the depot, waiting to leave
+    Departing,
+    EnRoute,
+    /// Delivering until the given time
+    Stopped(f64),
+}
+
+/// Attached to delivery vehicles.
+/// They leave the depot, stop at each house and are removed once back at the depot.
+#[derive(Component, Clone, Debug)]
+pub struct DeliveryTour {
+    pub depot: Vec2,
+    /// Houses to deliver, in visiting order
+    pub stops: Vec<Vec2>,
+    /// Index of the stop being driven to, equal to the number of stops when going back
+    pub next: usize,
+    /// Stop on the driving lane instead of using a kerb spot.
+    /// Without it, the vehicle still stops on the lane when there is no free spot at a stop.
+    pub double_park: bool,
+    phase: DeliveryPhase,
+    /// Last lane driven on, where the next route starts from when double parked
+    lane: Option<LaneID>,
+}
+
+impl DeliveryTour {
+    pub fn destination(&self) -> Vec2 {
+        self.stops.get(self.next).copied().unwrap_or(self.depot)
+    }
+
+    pub fn is_returning(&self) -> bool {
+        self.next >= self.stops.len()
+    }
+}
+
+/// Delivery tours generation and their measurements
+pub struct Deliveries {
+    /// Picked at random among the houses if not set
+    pub depot: Option<HouseID>,
+    pub tours_per_hour: f32,
+    pub stops_per_tour: u32,
+    /// Time spent at each stop, in seconds
+    pub dwell_time: f32,
+    pub double_park: bool,
+
+    pub started: u32,
+    pub completed: u32,
+    pub stops_made: u32,
+    /// Total time delivery vehicles spent stopped on the driving lanes, in seconds
+    pub double_parked_time: f32,
+
+    pending: f32,
+}
+
+impl Default for Deliveries {
+    fn default() -> Self {
+        Self {
+            depot: None,
+            tours_per_hour: 0.0,
+            stops_per_tour: 5,
+            dwell_time: 60.0,
+            double_park: true,
+            started: 0,
+            completed: 0,
+            stops_made: 0,
+            double_parked_time: 0.0,
+            pending: 0.0,
+        }
+    }
+}
+
+/// Orders the stops to make a short round trip from the depot.
+/// Starts from the nearest neighbour tour then improves it with 2-opt moves.
+/// Returns the indices of the stops in visiting order.
+pub fn plan_tour(depot: Vec2, stops: &[Vec2]) -> Vec<usize> {
+    let mut order = nearest_neighbour_tour(depot, stops);
+    two_opt(depot, stops, &mut order);
+    order
+}
+
+/// Always goes to the closest stop not visited yet
+fn nearest_neighbour_tour(depot: Vec2, stops: &[Vec2]) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::with_capacity(stops.len());
+    let mut left: Vec<usize> = (0..stops.len()).collect();
+    let mut cur = depot;
+    while !left.is_empty() {
+        let (i, _) = left
+            .iter()
+            .enumerate()
+            .min_by(|(_, &a), (_, &b)| {
+                cur.distance(stops[a])
+                    .partial_cmp(&cur.distance(stops[b]))
+                    .unwrap()
+            })
+            .unwrap(); // Unwrap ok: left is not empty
+        let next = left.swap_remove(i);
+        cur = stops[next];
+        order.push(next);
+    }
+    order
+}
+
+/// Reverses parts of the tour as long as it makes it shorter
+fn two_opt(depot: Vec2, stops: &[Vec2], order: &mut [usize]) {
+    let pos = |order: &[usize], i: usize| -> Vec2 {
+        if i == 0 || i > order.len() {
+            depot
+        } else {
+            stops[order[i - 1]]
+        }
+    };
+
+    // Positions 1..=n are the stops, 0 and n+1 the depot
+    let n = order.len();
+    for _ in 0..100 {
+        let mut improved = false;
+        for i in 1..n {
+            for j in i + 1..=n {
+                let (a, b) = (pos(order, i - 1), pos(order, i));
+                let (c, d) = (pos(order, j), pos(order, j + 1));
+                if a.distance(c) + b.distance(d) < a.distance(b) + c.distance(d) - 0.01 {
+                    order[i - 1..j].reverse();
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+}
+
+fn house_pos(map: &Map, id: HouseID) -> Option<Vec2> {
+    map.houses().get(id).map(|h| h.entrance)
+}
+
+/// Spawns a vehicle parked near the depot which will deliver the given houses
+pub fn spawn_delivery(
+    world: &mut World,
+    depot: HouseID,
+    stops: &[HouseID],
+    double_park: bool,
+) -> Option<Entity> {
+    let map = world.read_resource::<Map>();
+    let pm = world.read_resource::<ParkingManagement>();
+
+    let depot = house_pos(&map, depot)?;
+    let stops: Vec<Vec2> = stops.iter().filter_map(|&h| house_pos(&map, h)).collect();
+    if stops.is_empty() {
+        return None;
+    }
+    let stops: Vec<Vec2> = plan_tour(depot, &stops)
+        .into_iter()
+        .map(|i| stops[i])
+        .collect();
+
+    let lane = map.closest_lane(depot, LaneKind::Driving)?;
+    let time = world.read_resource::<TimeInfo>().time;
+    let kind = world
+        .read_resource::<VehicleTypes>()
+        .by_name("van")
+        .unwrap_or(VehicleKind(0));
+
+    // Without a free spot near the depot, start stopped on the lane
+    let (trans, vehicle, phase) = match pm.reserve_near(lane, depot, &map) {
+        Some(spot_id) => {
+            let spot = map.parking.get(spot_id).unwrap(); // Unwrap ok: Gotten using reserve_near
+            (
+                Transform::new_cos_sin(spot.pos, spot.orientation),
+                VehicleComponent::new(kind, spot_id),
+                DeliveryPhase::Departing,
+            )
+        }
+        None => {
+            let l = &map.lanes()[lane];
+            let d = l.points.distance_along(l.points.project(depot));
+            let (pos, dir) = l.points.point_dir_along(d);
+            (
+                Transform::new_cos_sin(pos, dir),
+                VehicleComponent {
+                    ang_velocity: 0.0,
+                    steering_angle: 0.0,
+                    wait_time: 0.0,
+                    park_spot: None,
+                    state: VehicleState::Driving,
+                    kind,
+                },
+                DeliveryPhase::Stopped(time),
+            )
+        }
+    };
+    let parked = matches!(vehicle.state, VehicleState::Parked(_));
+    drop(map);
+    drop(pm);
+
+    let e = make_vehicle_entity(world, trans, vehicle, Itinerary::wait_until(time), !parked);
+
+    world
+        .write_storage::<DeliveryTour>()
+        .insert(
+            e,
+            DeliveryTour {
+                depot,
+                stops,
+                next: 0,
+                double_park,
+                phase,
+                lane: Some(lane),
+            },
+        )
+        .expect("Invalid entity ?");
+
+    Some(e)
+}
+
+/// Route along the driving lanes from `lane` to the point of the road closest to `dest`
+pub(crate) fn route_to(pos: Vec2, lane: LaneID, dest: Vec2, map: &Map) -> Option<Itinerary> {
+    let travers = Traversable::new(TraverseKind::Lane(lane), TraverseDirection::Forward);
+    if !travers.is_valid(map) {
+        return None;
+    }
+    let end = &map.lanes()[map.closest_lane(dest, LaneKind::Driving)?];
+    Itinerary::route(
+        pos,
+        travers,
+        (end.id, end.points.project(dest)),
+        map,
+        &DirectionalPath,
+    )
+}
+
+/// Picks a random house, other than `except`
+fn random_house(map: &Map, rng: &mut RandProvider, except: Option<HouseID>) -> Option<HouseID> {
+    let candidates: Vec<HouseID> = map.houses().keys().filter(|&h| Some(h) != except).collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    Some(candidates[rng.rand_range(0, candidates.len() as i64) as usize])
+}
+
+pub struct DeliverySystem;
+
+#[derive(SystemData)]
+pub struct DeliverySystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    lazy: Read<'a, LazyUpdate>,
+    parking: Write<'a, ParkingManagement>,
+    deliveries: Write<'a, Deliveries>,
+    rng: Write<'a, RandProvider, PanicHandler>,
+    transforms: ReadStorage<'a, Transform>,
+    vehicles: WriteStorage<'a, VehicleComponent>,
+    itinerarys: WriteStorage<'a, Itinerary>,
+    tours: WriteStorage<'a, DeliveryTour>,
+}
+
+impl<'a> System<'a> for DeliverySystem {
+    type SystemData = DeliverySystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let time = data.time.time;
+        let dt = data.time.delta;
+        let map = &*data.map;
+        let deliveries = &mut *data.deliveries;
+
+        for (ent, trans, vehicle, it, tour) in (
+            &data.entities,
+            &data.transforms,
+            &mut data.vehicles,
+            &mut data.itinerarys,
+            &mut data.tours,
+        )
+            .join()
+        {
+            if let Some(TraverseKind::Lane(l)) = it.get_travers().map(|t| t.kind) {
+                tour.lane = Some(l);
+            }
+
+            match tour.phase {
+                DeliveryPhase::Departing => {
+                    if !matches!(vehicle.state, VehicleState::Parked(_)) {
+                        tour.phase = DeliveryPhase::EnRoute;
+                    }
+                }
+                DeliveryPhase::EnRoute => {
+                    if tour.double_park {
+                        // Driving to a kerb spot near the stop was set when unparking
+                        if let Some(spot) = vehicle.park_spot.take() {
+                            data.parking.free(spot);
+                        }
+                    }
+
+                    let arrived = match vehicle.state {
+                        VehicleState::Parked(_) => true,
+                        VehicleState::Driving => {
+                            vehicle.park_spot.is_none()
+                                && matches!(it.kind(), ItineraryKind::WaitUntil(_))
+                        }
+                        _ => false,
+                    };
+                    if !arrived {
+                        continue;
+                    }
+
+                    if tour.is_returning() {
+                        deliveries.completed += 1;
+                        data.lazy.exec_mut(move |world| delete_entity(world, ent));
+                        continue;
+                    }
+
+                    deliveries.stops_made += 1;
+                    tour.next += 1;
+                    let until = time + deliveries.dwell_time as f64;
+                    *it = Itinerary::wait_until(until);
+                    tour.phase = DeliveryPhase::Stopped(until);
+                }
+                DeliveryPhase::Stopped(until) => {
+                    if !matches!(vehicle.state, VehicleState::Driving) {
+                        // Unparking to the next stop is done by the vehicle decision system
+                        if !matches!(vehicle.state, VehicleState::Parked(_)) {
+                            tour.phase = DeliveryPhase::EnRoute;
+                        }
+                        continue;
+                    }
+
+                    deliveries.double_parked_time += dt;
+                    if time < until {
+                        continue;
+                    }
+
+                    let route = tour
+                        .lane
+                        .and_then(|l| route_to(trans.position(), l, tour.destination(), map));
+                    match route {
+                        Some(route) => {
+                            *it = route;
+                            tour.phase = DeliveryPhase::EnRoute;
+                        }
+                        None => {
+                            let until = time + 5.0;
+                            *it = Itinerary::wait_until(until);
+                            tour.phase = DeliveryPhase::Stopped(until);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Generate new tours
+        deliveries.pending += deliveries.tours_per_hour * dt / 3600.0;
+        while deliveries.pending >= 1.0 {
+            deliveries.pending -= 1.0;
+
+            if deliveries
+                .depot
+                .map_or(true, |d| !map.houses().contains_key(d))
+            {
+                deliveries.depot = random_house(map, &mut data.rng, None);
+            }
+            let depot = unwrap_or!(deliveries.depot, break);
+
+            let stops: Vec<HouseID> = (0..deliveries.stops_per_tour)
+                .filter_map(|_| random_house(map, &mut data.rng, Some(depot)))
+                .collect();
+            let double_park = deliveries.double_park;
+
+            data.lazy.exec_mut(move |world| {
+                spawn_delivery(world, depot, &stops, double_park);
+            });
+            deliveries.started += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_neighbour_tour, plan_tour};
+    use geom::{vec2, Vec2};
+
+    fn tour_length(depot: Vec2, stops: &[Vec2], order: &[usize]) -> f32 {
+        let mut points = vec![depot];
+        points.extend(order.iter().map(|&i| stops[i]));
+        points.push(depot);
+        points.windows(2).map(|w| w[0].distance(w[1])).sum()
+    }
+
+    fn is_permutation(order: &[usize], n: usize) -> bool {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        sorted == (0..n).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn no_stops() {
+        assert!(plan_tour(Vec2::ZERO, &[]).is_empty());
+    }
+
+    #[test]
+    fn one_stop() {
+        assert_eq!(plan_tour(Vec2::ZERO, &[vec2(10.0, 0.0)]), vec![0]);
+    }
+
+    #[test]
+    fn two_stops() {
+        let stops = [vec2(50.0, 0.0), vec2(10.0, 0.0)];
+        let order = plan_tour(Vec2::ZERO, &stops);
+        assert!(is_permutation(&order, 2));
+        assert!((tour_length(Vec2::ZERO, &stops, &order) - 100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn two_opt_never_longer() {
+        let depot = vec2(0.0, 0.0);
+        let stops = [
+            vec2(10.0, 0.0),
+            vec2(-12.0, 1.0),
+            vec2(30.0, 5.0),
+            vec2(0.0, 40.0),
+            vec2(-35.0, 30.0),
+            vec2(25.0, -20.0),
+            vec2(-5.0, -45.0),
+            vec2(50.0, 45.0),
+            vec2(15.0, 15.0),
+        ];
+        for n in 0..=stops.len() {
+            let stops = &stops[..n];
+            let nn = nearest_neighbour_tour(depot, stops);
+            let planned = plan_tour(depot, stops);
+            assert!(is_permutation(&planned, n));
+            assert!(
+                tour_length(depot, stops, &planned) <= tour_length(depot, stops, &nn) + 0.01,
+                "2-opt made the tour of {} stops longer",
+                n
+            );
+        }
+    }
+}
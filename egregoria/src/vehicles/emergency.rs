@@ -0,0 +1,142 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::Itinerary;
+use crate::physics::Transform;
+use crate::utils::delete_entity;
+use crate::vehicles::{make_vehicle_entity, VehicleComponent, VehicleState, VehicleTypes};
+use geom::Vec2;
+use map_model::{DirectionalPath, LaneKind, Map, Traversable, TraverseDirection, TraverseKind};
+use specs::prelude::*;
+
+/// Distance to the call location under which an emergency vehicle is considered arrived
+const ARRIVAL_DIST: f32 = 15.0;
+
+pub struct EmergencyCall {
+    /// Point of the road closest to the call location
+    pub target: Vec2,
+    pub called: f64,
+    pub arrived: Option<f64>,
+    /// None if the vehicle was removed before arriving
+    pub vehicle: Option<Entity>,
+}
+
+impl EmergencyCall {
+    /// Time between the call and the arrival on site, in seconds
+    pub fn response_time(&self) -> Option<f32> {
+        self.arrived.map(|t| (t - self.called) as f32)
+    }
+}
+
+/// Emergency vehicles sent from the station by the dispatch tool
+#[derive(Default)]
+pub struct Emergencies {
+    pub station: Option<Vec2>,
+    pub calls: Vec<EmergencyCall>,
+}
+
+impl Emergencies {
+    pub fn mean_response_time(&self) -> Option<f32> {
+        let times: Vec<f32> = self
+            .calls
+            .iter()
+            .filter_map(EmergencyCall::response_time)
+            .collect();
+        if times.is_empty() {
+            return None;
+        }
+        Some(times.iter().sum::<f32>() / times.len() as f32)
+    }
+}
+
+/// Sends an emergency vehicle from the station to the given location
+pub fn dispatch_emergency(world: &mut World, target: Vec2) -> Option<Entity> {
+    let station = world.read_resource::<Emergencies>().station?;
+    let kind = unwrap_or!(world.read_resource::<VehicleTypes>().emergency_kind(), {
+        error!("no emergency vehicle type declared in lua/vehicles.lua");
+        return None;
+    });
+
+    let map = world.read_resource::<Map>();
+
+    let start = map.closest_lane(station, LaneKind::Driving)?;
+    let end = &map.lanes()[map.closest_lane(target, LaneKind::Driving)?];
+
+    let travers = Traversable::new(TraverseKind::Lane(start), TraverseDirection::Forward);
+    let points = travers.points(&map);
+    let (pos, dir) = points.point_dir_along(points.distance_along(points.project(station)));
+
+    let target = end.points.project(target);
+    let it = Itinerary::route(pos, travers, (end.id, target), &map, &DirectionalPath);
+    let it = unwrap_or!(it, {
+        info!("no route from the station to {:?}", target);
+        return None;
+    });
+    let time = world.read_resource::<TimeInfo>().time;
+    drop(map);
+
+    let e = make_vehicle_entity(
+        world,
+        Transform::new_cos_sin(pos, dir),
+        VehicleComponent {
+            ang_velocity: 0.0,
+            steering_angle: 0.0,
+            wait_time: 0.0,
+            park_spot: None,
+            state: VehicleState::Driving,
+            kind,
+        },
+        it,
+        true,
+    );
+
+    world
+        .write_resource::<Emergencies>()
+        .calls
+        .push(EmergencyCall {
+            target,
+            called: time,
+            arrived: None,
+            vehicle: Some(e),
+        });
+
+    Some(e)
+}
+
+/// Records the arrival of the emergency vehicles and removes them
+pub struct EmergencySystem;
+
+#[derive(SystemData)]
+pub struct EmergencySystemData<'a> {
+    entities: Entities<'a>,
+    time: Read<'a, TimeInfo>,
+    lazy: Read<'a, LazyUpdate>,
+    emergencies: Write<'a, Emergencies>,
+    transforms: ReadStorage<'a, Transform>,
+}
+
+impl<'a> System<'a> for EmergencySystem {
+    type SystemData = EmergencySystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        for call in &mut data.emergencies.calls {
+            let e = unwrap_or!(call.vehicle, continue);
+            if call.arrived.is_some() {
+                continue;
+            }
+            if !data.entities.is_alive(e) {
+                call.vehicle = None;
+                continue;
+            }
+
+            let pos = unwrap_or!(data.transforms.get(e), continue).position();
+            if pos.distance(call.target) < ARRIVAL_DIST {
+                call.arrived = Some(data.time.time);
+                info!(
+                    "emergency vehicle arrived at {:?} after {:.1}s",
+                    call.target,
+                    data.time.time - call.called
+                );
+                data.lazy.exec_mut(move |world| delete_entity(world, e));
+            }
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::{Itinerary, ParkingManagement};
+use crate::physics::{Collider, CollisionWorld, Kinematics, Transform};
+use crate::rand_provider::RandProvider;
+use crate::stats::{GridlockEvent, TrafficStats};
+use crate::utils::delete_entity;
+use crate::vehicles::systems::{detour_objective, random_target};
+use crate::vehicles::{VehicleComponent, VehicleState};
+use geom::Vec2;
+use map_model::{Map, Traversable, TraverseDirection, TraverseKind, TurnID};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use specs::shred::PanicHandler;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+/// Speed under which a vehicle is considered stopped, in m/s
+const STOPPED_SPEED: f32 = 0.1;
+
+/// Seconds of simulated time between two detections
+const CHECK_INTERVAL: f32 = 1.0;
+
+/// Distance between two vehicles for one to be considered blocked by the other
+const BLOCKING_DIST: f32 = 3.0;
+
+/// Distance from the route of a vehicle under which another vehicle is on it
+const ON_ROUTE_DIST: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GridlockPolicy {
+    Ignore,
+    /// Give one vehicle of the cycle a new route through another turn, and remove it if the
+    /// cycle is still there at the next check
+    Reroute,
+    /// Remove one vehicle of the cycle
+    Despawn,
+}
+
+impl AsRef<str> for GridlockPolicy {
+    fn as_ref(&self) -> &str {
+        match self {
+            GridlockPolicy::Ignore => "Ignore",
+            GridlockPolicy::Reroute => "Reroute",
+            GridlockPolicy::Despawn => "Despawn",
+        }
+    }
+}
+
+/// Cycles of vehicles waiting on each other, found by the `GridlockSystem`
+pub struct Gridlocks {
+    pub policy: GridlockPolicy,
+    /// Time a vehicle must have been stopped before being part of a gridlock, in seconds
+    pub min_wait: f32,
+    pub cycles: Vec<Vec<Entity>>,
+}
+
+impl Default for Gridlocks {
+    fn default() -> Self {
+        Self {
+            policy: GridlockPolicy::Ignore,
+            min_wait: 10.0,
+            cycles: vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GridlockSystem {
+    stopped_for: HashMap<Entity, f32>,
+    since_check: f32,
+    known: HashSet<Vec<Entity>>,
+    /// Cycles where a vehicle was given a new route at the last check
+    rerouted: HashSet<Vec<Entity>>,
+}
+
+#[derive(SystemData)]
+pub struct GridlockSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    parking: Write<'a, ParkingManagement>,
+    lazy: Read<'a, LazyUpdate>,
+    coworld: Read<'a, CollisionWorld, PanicHandler>,
+    gridlocks: Write<'a, Gridlocks>,
+    stats: Write<'a, TrafficStats>,
+    rng: Write<'a, RandProvider, PanicHandler>,
+    transforms: ReadStorage<'a, Transform>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    colliders: ReadStorage<'a, Collider>,
+    vehicles: WriteStorage<'a, VehicleComponent>,
+    itinerarys: WriteStorage<'a, Itinerary>,
+}
+
+impl<'a> System<'a> for GridlockSystem {
+    type SystemData = GridlockSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let dt = data.time.delta;
+
+        for (ent, vehicle, kin) in (&data.entities, &data.vehicles, &data.kinematics).join() {
+            if matches!(vehicle.state, VehicleState::Driving)
+                && kin.velocity.magnitude() < STOPPED_SPEED
+            {
+                *self.stopped_for.entry(ent).or_default() += dt;
+            } else {
+                self.stopped_for.remove(&ent);
+            }
+        }
+        let entities = &data.entities;
+        self.stopped_for.retain(|&e, _| entities.is_alive(e));
+
+        self.since_check += dt;
+        if self.since_check < CHECK_INTERVAL {
+            return;
+        }
+        self.since_check = 0.0;
+
+        let min_wait = data.gridlocks.min_wait;
+        let waiting: HashMap<_, Entity> = self
+            .stopped_for
+            .iter()
+            .filter(|(_, &t)| t >= min_wait)
+            .filter_map(|(&e, _)| Some((data.colliders.get(e)?.0, e)))
+            .collect();
+
+        // Each waiting vehicle waits on at most one other: the closest stopped vehicle in front,
+        // on its current or next traversable. Ordered so that the cycles are found, and the
+        // random detours drawn, in the same order every run.
+        let mut waits_on: BTreeMap<Entity, Entity> = BTreeMap::new();
+        for (&h, &e) in &waiting {
+            let trans = unwrap_or!(data.transforms.get(e), continue);
+            let (_, obj) = unwrap_or!(data.coworld.get(h), continue);
+            let it = unwrap_or!(data.itinerarys.get(e), continue);
+            let pos = trans.position();
+            let dir = trans.direction();
+            let map = &data.map;
+            let route: Vec<_> = it
+                .get_travers()
+                .into_iter()
+                .chain(it.peek_next())
+                .filter(|t| t.is_valid(map))
+                .map(|t| t.points(map))
+                .collect();
+
+            let blocker = data
+                .coworld
+                .query_around(pos, obj.radius + BLOCKING_DIST + 5.0)
+                .filter(|&(oh, _)| oh != h)
+                .filter_map(|(oh, opos)| {
+                    let other = *waiting.get(&oh)?;
+                    let (_, oobj) = data.coworld.get(oh)?;
+                    if !oobj.group.is_vehicle() {
+                        return None;
+                    }
+                    let (towards, dist) = (Vec2::from(opos) - pos).dir_dist()?;
+                    if towards.dot(dir) < 0.5 || dist - obj.radius - oobj.radius > BLOCKING_DIST {
+                        return None;
+                    }
+                    let opos = Vec2::from(opos);
+                    if !route
+                        .iter()
+                        .any(|p| p.project(opos).distance(opos) < ON_ROUTE_DIST)
+                    {
+                        return None;
+                    }
+                    Some((other, dist))
+                })
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .map(|x| x.0);
+
+            if let Some(blocker) = blocker {
+                waits_on.insert(e, blocker);
+            }
+        }
+
+        let cycles = find_cycles(&waits_on);
+
+        let policy = data.gridlocks.policy;
+        let mut known = HashSet::new();
+        let mut rerouted = HashSet::new();
+        for cycle in &cycles {
+            let mut key = cycle.clone();
+            key.sort();
+            // Unblocking any vehicle of the cycle is enough
+            let victim = key[0];
+
+            if self.rerouted.contains(&key) {
+                info!(
+                    "gridlock of {} vehicles still there after rerouting, removing {:?}",
+                    cycle.len(),
+                    victim
+                );
+                data.lazy
+                    .exec_mut(move |world| delete_entity(world, victim));
+                continue;
+            }
+            if self.known.contains(&key) {
+                known.insert(key);
+                continue;
+            }
+
+            let positions: Vec<Vec2> = key
+                .iter()
+                .filter_map(|&e| data.transforms.get(e))
+                .map(|x| x.position())
+                .collect();
+            let center = positions.iter().fold(Vec2::ZERO, |acc, &p| acc + p)
+                / positions.len().max(1) as f32;
+
+            info!(
+                "gridlock of {} vehicles detected at {:?}",
+                cycle.len(),
+                center
+            );
+            data.stats.gridlock(GridlockEvent {
+                time: data.time.time,
+                pos: center,
+                n_vehicles: cycle.len() as u32,
+                policy,
+            });
+
+            match policy {
+                GridlockPolicy::Ignore => {
+                    known.insert(key);
+                }
+                GridlockPolicy::Reroute => {
+                    let trans = unwrap_or!(data.transforms.get(victim), continue);
+                    let vehicle = unwrap_or!(data.vehicles.get_mut(victim), continue);
+                    let it = unwrap_or!(data.itinerarys.get_mut(victim), continue);
+
+                    let map = &data.map;
+                    let parking = &data.parking;
+                    let rng = &mut *data.rng;
+                    let detour = other_turn(it, map).and_then(|(cur, via)| {
+                        let target = random_target(map, rng)?;
+                        detour_objective(trans.position(), parking, map, cur, via, target)
+                    });
+                    match detour {
+                        Some((new_it, spot)) => {
+                            if let Some(old) = vehicle.park_spot.replace(spot) {
+                                data.parking.free(old);
+                            }
+                            *it = new_it;
+                            rerouted.insert(key);
+                        }
+                        None => {
+                            info!("no other way out for {:?}, removing it", victim);
+                            data.lazy
+                                .exec_mut(move |world| delete_entity(world, victim));
+                        }
+                    }
+                }
+                GridlockPolicy::Despawn => {
+                    data.lazy
+                        .exec_mut(move |world| delete_entity(world, victim));
+                }
+            }
+        }
+
+        self.rerouted = rerouted;
+        self.known = known;
+        data.gridlocks.cycles = cycles;
+    }
+}
+
+/// Current lane of the itinerary and a turn out of it other than the one it was going to take
+fn other_turn(it: &Itinerary, map: &Map) -> Option<(Traversable, TurnID)> {
+    let cur = *it.get_travers()?;
+    let lane = match cur.kind {
+        TraverseKind::Lane(id) => map.lanes().get(id)?,
+        TraverseKind::Turn(_) => return None,
+    };
+    let blocked = it.peek_next().and_then(|t| match t.kind {
+        TraverseKind::Turn(id) => Some(id),
+        TraverseKind::Lane(_) => None,
+    });
+
+    let via = map
+        .intersections()
+        .get(lane.dst)?
+        .turns_from(lane.id)
+        .filter(|&(id, dir)| dir == TraverseDirection::Forward && Some(id) != blocked)
+        .map(|(id, _)| id)
+        .next()?;
+
+    Some((cur, via))
+}
+
+/// Finds the cycles of a graph where each node has at most one successor
+fn find_cycles(next: &BTreeMap<Entity, Entity>) -> Vec<Vec<Entity>> {
+    let mut cycles = vec![];
+    let mut visited: BTreeSet<Entity> = BTreeSet::new();
+
+    for &start in next.keys() {
+        if visited.contains(&start) {
+            continue;
+        }
+
+        let mut path: Vec<Entity> = vec![];
+        let mut cur = start;
+        loop {
+            if let Some(idx) = path.iter().position(|&x| x == cur) {
+                cycles.push(path.split_off(idx));
+                break;
+            }
+            if !visited.insert(cur) {
+                break;
+            }
+            path.push(cur);
+            cur = *unwrap_or!(next.get(&cur), break);
+        }
+    }
+
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_cycles;
+    use specs::{Builder, Entity, World, WorldExt};
+    use std::collections::BTreeMap;
+
+    fn entities(n: usize) -> Vec<Entity> {
+        let mut world = World::new();
+        (0..n).map(|_| world.create_entity().build()).collect()
+    }
+
+    fn sorted(mut cycles: Vec<Vec<Entity>>) -> Vec<Vec<Entity>> {
+        for c in &mut cycles {
+            c.sort();
+        }
+        cycles.sort();
+        cycles
+    }
+
+    #[test]
+    fn self_loop() {
+        let e = entities(1);
+        let next: BTreeMap<_, _> = vec![(e[0], e[0])].into_iter().collect();
+        assert_eq!(find_cycles(&next), vec![vec![e[0]]]);
+    }
+
+    #[test]
+    fn chain_into_cycle() {
+        let e = entities(4);
+        // 0 -> 1 -> 2 -> 3 -> 2
+        let next: BTreeMap<_, _> = vec![(e[0], e[1]), (e[1], e[2]), (e[2], e[3]), (e[3], e[2])]
+            .into_iter()
+            .collect();
+        assert_eq!(sorted(find_cycles(&next)), vec![vec![e[2], e[3]]]);
+    }
+
+    #[test]
+    fn disjoint_cycles() {
+        let e = entities(5);
+        // 0 -> 1 -> 0 and 2 -> 3 -> 4 -> 2
+        let next: BTreeMap<_, _> = vec![
+            (e[0], e[1]),
+            (e[1], e[0]),
+            (e[2], e[3]),
+            (e[3], e[4]),
+            (e[4], e[2]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            sorted(find_cycles(&next)),
+            vec![vec![e[0], e[1]], vec![e[2], e[3], e[4]]]
+        );
+    }
+
+    #[test]
+    fn no_cycle() {
+        let e = entities(4);
+        // 0 -> 1 -> 2 and 3 -> 2
+        let next: BTreeMap<_, _> = vec![(e[0], e[1]), (e[1], e[2]), (e[3], e[2])]
+            .into_iter()
+            .collect();
+        assert!(find_cycles(&next).is_empty());
+    }
+}
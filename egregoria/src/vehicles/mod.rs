@@ -1,11 +1,29 @@
 use specs::World;
 
+mod car_following;
 mod data;
+mod delivery;
+mod emergency;
+mod gridlock;
+mod od_matrix;
+mod reservation;
 mod saveload;
+mod steering;
+mod stop_sign;
 pub mod systems;
+mod types;
 
+pub use car_following::*;
 pub use data::*;
+pub use delivery::*;
+pub use emergency::*;
+pub use gridlock::*;
+pub use od_matrix::*;
+pub use reservation::*;
 pub use saveload::*;
+pub use steering::*;
+pub use stop_sign::*;
+pub use types::*;
 
 pub fn setup(world: &mut World) {
     load(world);
@@ -0,0 +1,305 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::{Itinerary, ParkingManagement};
+use crate::physics::Transform;
+use crate::utils::delete_entity;
+use crate::vehicles::{
+    make_vehicle_entity, VehicleComponent, VehicleKind, VehicleState, VehicleTypes,
+};
+use crate::RandProvider;
+use geom::polygon::Polygon;
+use geom::{vec2, Vec2};
+use map_model::{IntersectionID, LaneKind, Map};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+use specs::shred::PanicHandler;
+use specs::{Component, DenseVecStorage};
+use std::path::Path;
+
+/// Attached to vehicles spawned from the OD matrix.
+/// They drive to their destination once and are removed when they have parked there.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ODTrip {
+    pub destination: Vec2,
+    pub departed: bool,
+}
+
+pub enum Zone {
+    Polygon(Polygon),
+    Intersections(Vec<IntersectionID>),
+}
+
+impl Zone {
+    /// Random point inside the zone
+    pub fn sample(&self, map: &Map, rng: &mut RandProvider) -> Option<Vec2> {
+        match self {
+            Zone::Polygon(p) => {
+                let bbox = p.bbox();
+                (0..20)
+                    .map(|_| {
+                        vec2(
+                            bbox.x + rng.random::<f32>() * bbox.w,
+                            bbox.y + rng.random::<f32>() * bbox.h,
+                        )
+                    })
+                    .find(|&pos| p.contains(pos))
+            }
+            Zone::Intersections(inters) => {
+                if inters.is_empty() {
+                    return None;
+                }
+                let id = inters[rng.rand_range(0, inters.len() as i64) as usize];
+                map.intersections().get(id).map(|x| x.pos)
+            }
+        }
+    }
+}
+
+pub struct ODPair {
+    pub origin: usize,
+    pub destination: usize,
+    pub trips_per_hour: f32,
+    /// Fraction of a trip not yet spawned
+    pending: f32,
+    /// Whether a failed trip of this pair was logged already
+    failure_logged: bool,
+}
+
+/// Origin-destination matrix used to generate vehicle trips over simulated time.
+#[derive(Default)]
+pub struct ODMatrix {
+    pub zones: Vec<(String, Zone)>,
+    pub pairs: Vec<ODPair>,
+    pub spawned: u32,
+    pub completed: u32,
+    /// Trips that couldn't be made, because a zone has no valid point or no parking spot
+    /// is available at the origin
+    pub failed: u32,
+}
+
+fn parse_points(s: &str) -> Result<Vec<Vec2>, String> {
+    s.split(';')
+        .filter(|x| !x.trim().is_empty())
+        .map(|p| {
+            let mut coords = p.split_whitespace().map(str::parse::<f32>);
+            match (coords.next(), coords.next(), coords.next()) {
+                (Some(Ok(x)), Some(Ok(y)), None) => Ok(vec2(x, y)),
+                _ => Err(format!("invalid point `{}`, expected `x y`", p.trim())),
+            }
+        })
+        .collect()
+}
+
+/// Non empty lines of a csv file, with the header removed
+fn csv_lines(path: &Path) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let content =
+        std::fs::read_to_string(path).map_err(|e| format!("could not read {:?}: {}", path, e))?;
+    Ok(content
+        .lines()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| (i + 1, l.split(',').map(|x| x.trim().to_string()).collect()))
+        .collect())
+}
+
+impl ODMatrix {
+    /// Loads the zones and the trips from two csv files.
+    ///
+    /// zones: `zone,kind,points` where kind is `polygon` or `intersections` and points are
+    /// separated by `;` like `0 0;100 0;100 100`. Intersection zones use the intersections
+    /// closest to the given points.
+    ///
+    /// matrix: `origin,destination,trips_per_hour`
+    pub fn from_csv(
+        zones: impl AsRef<Path>,
+        matrix: impl AsRef<Path>,
+        map: &Map,
+    ) -> Result<ODMatrix, String> {
+        let zones_path = zones.as_ref();
+        let mut zones = vec![];
+        for (line, fields) in csv_lines(zones_path)? {
+            let err = |e: String| format!("{:?} line {}: {}", zones_path, line, e);
+            if fields.len() != 3 {
+                return Err(err("expected zone,kind,points".to_string()));
+            }
+            let points = parse_points(&fields[2]).map_err(err)?;
+            let zone = match fields[1].as_str() {
+                "polygon" => {
+                    if points.len() < 3 {
+                        return Err(err("a polygon needs at least 3 points".to_string()));
+                    }
+                    Zone::Polygon(Polygon::from(points))
+                }
+                "intersections" => Zone::Intersections(
+                    points
+                        .into_iter()
+                        .map(|p| {
+                            map.intersections()
+                                .iter()
+                                .min_by_key(|(_, inter)| OrderedFloat(inter.pos.distance2(p)))
+                                .map(|(id, _)| id)
+                                .ok_or_else(|| err("the map has no intersections".to_string()))
+                        })
+                        .collect::<Result<Vec<_>, String>>()?,
+                ),
+                kind => {
+                    return Err(err(format!(
+                        "unknown zone kind `{}`, expected polygon or intersections",
+                        kind
+                    )))
+                }
+            };
+            zones.push((fields[0].clone(), zone));
+        }
+
+        let matrix_path = matrix.as_ref();
+        let zone_index = |name: &str| zones.iter().position(|(zname, _)| zname == name);
+        let mut pairs = vec![];
+        for (line, fields) in csv_lines(matrix_path)? {
+            let err = |e: String| format!("{:?} line {}: {}", matrix_path, line, e);
+            if fields.len() != 3 {
+                return Err(err("expected origin,destination,trips_per_hour".to_string()));
+            }
+            let origin = zone_index(&fields[0])
+                .ok_or_else(|| err(format!("unknown zone `{}`", fields[0])))?;
+            let destination = zone_index(&fields[1])
+                .ok_or_else(|| err(format!("unknown zone `{}`", fields[1])))?;
+            let trips_per_hour = fields[2]
+                .parse::<f32>()
+                .map_err(|_| err(format!("invalid trip count `{}`", fields[2])))?;
+
+            pairs.push(ODPair {
+                origin,
+                destination,
+                trips_per_hour,
+                pending: 0.0,
+                failure_logged: false,
+            });
+        }
+
+        Ok(ODMatrix {
+            zones,
+            pairs,
+            spawned: 0,
+            completed: 0,
+            failed: 0,
+        })
+    }
+
+    pub fn trips_per_hour(&self) -> f32 {
+        self.pairs.iter().map(|x| x.trips_per_hour).sum()
+    }
+}
+
+/// Spawns a parked vehicle close to `from` which will drive to `to`
+pub fn spawn_trip_vehicle(world: &mut World, from: Vec2, to: Vec2) -> Option<Entity> {
+    let map = world.read_resource::<Map>();
+    let pm = world.read_resource::<ParkingManagement>();
+
+    let lane = map.closest_lane(from, LaneKind::Driving)?;
+    let spot_id = pm.reserve_near(lane, from, &map)?;
+
+    let spot = map.parking.get(spot_id).unwrap(); // Unwrap ok: Gotten using reserve_near
+    let trans = Transform::new_cos_sin(spot.pos, spot.orientation);
+    let time = world.read_resource::<TimeInfo>().time;
+    let kind = world
+        .read_resource::<VehicleTypes>()
+        .random_kind(&mut world.write_resource::<RandProvider>().rng)
+        .unwrap_or(VehicleKind(0));
+    drop(map);
+    drop(pm);
+
+    let e = make_vehicle_entity(
+        world,
+        trans,
+        VehicleComponent::new(kind, spot_id),
+        Itinerary::wait_until(time),
+        false,
+    );
+
+    world
+        .write_storage::<ODTrip>()
+        .insert(
+            e,
+            ODTrip {
+                destination: to,
+                departed: false,
+            },
+        )
+        .expect("Invalid entity ?");
+
+    Some(e)
+}
+
+pub struct ODSpawnSystem;
+
+#[derive(SystemData)]
+pub struct ODSpawnSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    lazy: Read<'a, LazyUpdate>,
+    od: Write<'a, ODMatrix>,
+    rng: Write<'a, RandProvider, PanicHandler>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+    trips: WriteStorage<'a, ODTrip>,
+}
+
+impl<'a> System<'a> for ODSpawnSystem {
+    type SystemData = ODSpawnSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let od = &mut *data.od;
+
+        // Remove the vehicles that are parked at their destination
+        for (ent, vehicle, trip) in (&data.entities, &data.vehicles, &mut data.trips).join() {
+            if let VehicleState::Parked(_) = vehicle.state {
+                if trip.departed {
+                    od.completed += 1;
+                    data.lazy.exec_mut(move |world| delete_entity(world, ent));
+                }
+            } else {
+                trip.departed = true;
+            }
+        }
+
+        let dt = data.time.delta;
+        let zones = &od.zones;
+        let mut failed = 0;
+        for pair in &mut od.pairs {
+            pair.pending += pair.trips_per_hour * dt / 3600.0;
+            while pair.pending >= 1.0 {
+                pair.pending -= 1.0;
+
+                let from = zones[pair.origin].1.sample(&data.map, &mut data.rng);
+                let to = zones[pair.destination].1.sample(&data.map, &mut data.rng);
+                let (from, to) = match (from, to) {
+                    (Some(from), Some(to)) => (from, to),
+                    _ => {
+                        failed += 1;
+                        if !pair.failure_logged {
+                            pair.failure_logged = true;
+                            warn!(
+                                "OD pair {} -> {}: no point sampled in its zones, trip dropped",
+                                zones[pair.origin].0, zones[pair.destination].0
+                            );
+                        }
+                        continue;
+                    }
+                };
+
+                data.lazy.exec_mut(move |world| {
+                    let spawned = spawn_trip_vehicle(world, from, to).is_some();
+                    let mut od = world.write_resource::<ODMatrix>();
+                    if spawned {
+                        od.spawned += 1;
+                    } else {
+                        od.failed += 1;
+                    }
+                });
+            }
+        }
+        od.failed += failed;
+    }
+}
@@ -0,0 +1,214 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::Itinerary;
+use crate::physics::{Kinematics, Transform};
+use crate::vehicles::{VehicleComponent, VehicleState, VehicleTypes};
+use map_model::{IntersectionID, LaneID, Map, TrafficBehavior, TraverseKind, TurnID};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Distance to the intersection under which vehicles request a reservation, in meters
+const REQUEST_DIST: f32 = 60.0;
+
+/// Speed at which vehicles are expected to drive through the intersection, in m/s
+const CROSSING_SPEED: f32 = 8.0;
+
+/// Safety margin around each reservation, in seconds
+const MARGIN: f64 = 0.5;
+
+/// Minimum time between two vehicles entering the same turn, in seconds
+const HEADWAY: f64 = 1.5;
+
+/// A vehicle arriving later than this after its slot start loses it and asks again, in seconds
+const LATE_GRACE: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Reservation {
+    pub vehicle: Entity,
+    /// Lane the vehicle comes from
+    pub lane: LaneID,
+    pub turn: TurnID,
+    /// Time at which the vehicle may enter the turn
+    pub enter: f64,
+    /// Time at which the vehicle is expected to have left the turn
+    pub exit: f64,
+}
+
+impl Reservation {
+    fn overlaps(&self, enter: f64, exit: f64) -> bool {
+        enter < self.exit + MARGIN && self.enter < exit + MARGIN
+    }
+}
+
+/// Grants non-conflicting space-time slots along the turns of an intersection
+#[derive(Default, Debug)]
+pub struct ReservationManager {
+    pub granted: Vec<Reservation>,
+}
+
+impl ReservationManager {
+    pub fn get(&self, vehicle: Entity) -> Option<&Reservation> {
+        self.granted.iter().find(|r| r.vehicle == vehicle)
+    }
+
+    /// Earliest slot starting after `earliest` lasting `duration` that doesn't conflict
+    /// with the already granted reservations
+    fn first_free_slot(
+        &self,
+        map: &Map,
+        turn: TurnID,
+        earliest: f64,
+        duration: f64,
+    ) -> Option<f64> {
+        let inter = map.intersections().get(turn.parent)?;
+
+        let blocking: Vec<&Reservation> = self
+            .granted
+            .iter()
+            .filter(|r| r.turn == turn || inter.conflicts(turn).any(|(id, _)| id == r.turn))
+            .collect();
+
+        let mut enter = earliest;
+        // Each retry starts after the end of a blocking reservation, so it terminates
+        loop {
+            let conflict = blocking.iter().find(|r| {
+                if r.turn == turn {
+                    (r.enter - enter).abs() < HEADWAY
+                } else {
+                    r.overlaps(enter, enter + duration)
+                }
+            });
+            match conflict {
+                Some(r) if r.turn == turn => enter = r.enter + HEADWAY,
+                Some(r) => enter = r.exit + MARGIN,
+                None => return Some(enter),
+            }
+        }
+    }
+}
+
+/// Reservation managers of the intersections using `LightPolicy::Reservations`
+#[derive(Default)]
+pub struct IntersectionReservations {
+    pub managers: BTreeMap<IntersectionID, ReservationManager>,
+}
+
+impl IntersectionReservations {
+    pub fn get(&self, inter: IntersectionID, vehicle: Entity) -> Option<&Reservation> {
+        self.managers.get(&inter)?.get(vehicle)
+    }
+}
+
+pub struct ReservationSystem;
+
+#[derive(SystemData)]
+pub struct ReservationSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    types: Read<'a, VehicleTypes>,
+    reservations: Write<'a, IntersectionReservations>,
+    transforms: ReadStorage<'a, Transform>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+    itinerarys: ReadStorage<'a, Itinerary>,
+}
+
+impl<'a> System<'a> for ReservationSystem {
+    type SystemData = ReservationSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let map = &*data.map;
+        let now = data.time.time;
+        let managers = &mut data.reservations.managers;
+        let entities = &data.entities;
+        let itinerarys = &data.itinerarys;
+
+        // Free the slots of the vehicles that went through, were removed or are late
+        for m in managers.values_mut() {
+            m.granted.retain(|r| {
+                if !entities.is_alive(r.vehicle) {
+                    return false;
+                }
+                match itinerarys
+                    .get(r.vehicle)
+                    .and_then(Itinerary::get_travers)
+                    .map(|t| t.kind)
+                {
+                    Some(TraverseKind::Turn(id)) => id == r.turn,
+                    Some(TraverseKind::Lane(id)) => id == r.lane && now < r.enter + LATE_GRACE,
+                    None => false,
+                }
+            });
+        }
+
+        for (ent, trans, kin, vehicle, it) in (
+            entities,
+            &data.transforms,
+            &data.kinematics,
+            &data.vehicles,
+            itinerarys,
+        )
+            .join()
+        {
+            if !matches!(vehicle.state, VehicleState::Driving) {
+                continue;
+            }
+            let ty = &data.types[vehicle.kind];
+            if ty.emergency {
+                continue;
+            }
+
+            let l_id = match it.get_travers().map(|t| t.kind) {
+                Some(TraverseKind::Lane(l)) => l,
+                _ => continue,
+            };
+            let turn = match it.peek_next().map(|t| t.kind) {
+                Some(TraverseKind::Turn(t)) => t,
+                _ => continue,
+            };
+            let lane = unwrap_or!(map.lanes().get(l_id), continue);
+            if !matches!(
+                lane.control.get_behavior(data.time.time_seconds),
+                TrafficBehavior::RESERVATION
+            ) {
+                continue;
+            }
+
+            let dist = lane.control_point().distance(trans.position());
+            if dist > REQUEST_DIST {
+                continue;
+            }
+
+            let m = managers.entry(lane.dst).or_default();
+            if m.get(ent).is_some() {
+                continue;
+            }
+
+            let turn_length = unwrap_or!(
+                map.intersections()
+                    .get(turn.parent)
+                    .and_then(|i| i.find_turn(turn)),
+                continue
+            )
+            .points
+            .length();
+
+            let speed = kin.velocity.magnitude().max(ty.cruising_speed * 0.5);
+            let earliest = now + (dist / speed) as f64;
+            let duration =
+                ((turn_length + ty.width) / CROSSING_SPEED.min(ty.cruising_speed)) as f64;
+
+            if let Some(enter) = m.first_free_slot(map, turn, earliest, duration) {
+                m.granted.push(Reservation {
+                    vehicle: ent,
+                    lane: l_id,
+                    turn,
+                    enter,
+                    exit: enter + duration,
+                });
+            }
+        }
+
+        managers.retain(|_, m| !m.granted.is_empty());
+    }
+}
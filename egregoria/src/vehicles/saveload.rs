@@ -1,9 +1,15 @@
-use crate::map_interaction::Itinerary;
+use crate::map_interaction::{Itinerary, ParkingManagement};
 use crate::physics::Transform;
-use crate::vehicles::VehicleComponent;
+use crate::vehicles::{make_vehicle_entity, VehicleComponent, VehicleState, VehicleTypes};
+use crate::UnreadableMap;
 use specs::{Join, World, WorldExt};
 
+/// Vehicle kinds are indices into the loaded types, which change with the enabled mods,
+/// so the names of the types are saved along with the vehicles
 pub fn save(world: &mut World) {
+    let types = world.read_resource::<VehicleTypes>();
+    let names: Vec<&str> = types.iter().map(|(_, ty)| ty.name.as_str()).collect();
+
     let storages = (
         &world.read_component::<Transform>(),
         &world.read_component::<VehicleComponent>(),
@@ -15,18 +21,43 @@ pub fn save(world: &mut World) {
         .map(|(trans, car, it)| (trans, car, it))
         .collect();
 
-    let _ = crate::saveload::save(&comps, "vehicles");
+    let _ = crate::saveload::save(&(names, comps), "vehicles");
 }
 
-pub fn load(_world: &mut World) {
-    /*
-    // FIXME: load parked cars and shit
-    let des = bincode::deserialize_from(file.unwrap());
+pub fn load(world: &mut World) {
+    if world.read_resource::<UnreadableMap>().0 {
+        return;
+    }
+
+    let (names, comps): (Vec<String>, Vec<(Transform, VehicleComponent, Itinerary)>) =
+        unwrap_or!(crate::saveload::load("vehicles"), return);
+
+    for (trans, mut car, it) in comps {
+        let kind = names
+            .get(car.kind.0 as usize)
+            .and_then(|name| world.read_resource::<VehicleTypes>().by_name(name));
+        car.kind = match kind {
+            Some(kind) => kind,
+            None => {
+                warn!(
+                    "removing a saved vehicle of unknown type {:?}",
+                    names.get(car.kind.0 as usize)
+                );
+                continue;
+            }
+        };
 
-    let comps: Vec<(Transform, VehicleComponent, Itinerary)> = des.unwrap_or_default();
+        {
+            let parking = world.read_resource::<ParkingManagement>();
+            if let VehicleState::Parked(spot) = car.state {
+                parking.reserve(spot);
+            }
+            if let Some(spot) = car.park_spot {
+                parking.reserve(spot);
+            }
+        }
 
-    for (trans, car, it) in comps {
-        make_vehicle_entity(world, trans, car, it);
+        let parked = matches!(car.state, VehicleState::Parked(_));
+        make_vehicle_entity(world, trans, car, it, !parked);
     }
-     */
 }
@@ -0,0 +1,107 @@
+use geom::{vec2, Vec2};
+
+/// Kinematic bicycle model: the front wheels turn at a limited rate and the heading follows them
+/// according to the wheelbase, so the vehicle can't turn in place.
+/// It is steered by pure pursuit over the local path of the itinerary.
+#[derive(Clone, Copy, Debug)]
+pub struct Bicycle {
+    /// Distance between the front and rear axles, in meters
+    pub wheelbase: f32,
+    /// Largest angle of the front wheels, in radians
+    pub max_steering_angle: f32,
+    /// How fast the front wheels turn, in rad/s
+    pub steering_rate: f32,
+    /// Look-ahead distance per m/s of speed, in seconds
+    pub lookahead_time: f32,
+    /// Look-ahead distance when stopped, in meters
+    pub min_lookahead: f32,
+}
+
+impl Bicycle {
+    /// Defaults for a vehicle of the given length
+    pub fn with_length(length: f32) -> Self {
+        Self {
+            wheelbase: length * 0.6,
+            max_steering_angle: 0.6,
+            steering_rate: 1.2,
+            lookahead_time: 0.6,
+            min_lookahead: 5.0,
+        }
+    }
+
+    fn lookahead(&self, speed: f32) -> f32 {
+        self.min_lookahead + speed.abs() * self.lookahead_time
+    }
+
+    /// Point of the path at the look-ahead distance from the rear axle, the last point if the path
+    /// is shorter than that
+    fn lookahead_point(&self, rear: Vec2, speed: f32, path: &[Vec2]) -> Option<Vec2> {
+        let ld = self.lookahead(speed);
+        let mut prev = rear;
+        for &p in path {
+            if p.distance(rear) >= ld {
+                // Intersection of the segment with the look-ahead circle
+                let d = p - prev;
+                let f = prev - rear;
+                let a = d.magnitude2();
+                let b = 2.0 * f.dot(d);
+                let c = f.magnitude2() - ld * ld;
+                let t = (-b + (b * b - 4.0 * a * c).max(0.0).sqrt()) / (2.0 * a).max(1e-6);
+                return Some(prev + d * t.max(0.0).min(1.0));
+            }
+            prev = p;
+        }
+        path.last().copied()
+    }
+
+    /// Angle of the front wheels bringing the rear axle onto the path, from pure pursuit.
+    /// `pos` is the center of the vehicle. The look-ahead point is rotated around the rear axle
+    /// by `aim`, as (cos, sin), to drive beside the path.
+    pub fn pure_pursuit(
+        &self,
+        pos: Vec2,
+        heading: Vec2,
+        speed: f32,
+        path: &[Vec2],
+        aim: Vec2,
+    ) -> f32 {
+        let rear = pos - heading * self.wheelbase * 0.5;
+        let target = unwrap_or!(self.lookahead_point(rear, speed, path), return 0.0);
+        let (dir, dist) = unwrap_or!((target - rear).dir_dist(), return 0.0);
+        let dir = dir.rotated_by(aim);
+
+        let alpha = heading.angle(dir);
+        (2.0 * self.wheelbase * alpha.sin() / dist)
+            .atan()
+            .max(-self.max_steering_angle)
+            .min(self.max_steering_angle)
+    }
+
+    /// Turns the front wheels towards `target` at the steering rate
+    pub fn turn_wheels(&self, steering_angle: f32, target: f32, dt: f32) -> f32 {
+        let max = self.steering_rate * dt;
+        (steering_angle + (target - steering_angle).max(-max).min(max))
+            .max(-self.max_steering_angle)
+            .min(self.max_steering_angle)
+    }
+
+    /// Heading after `dt` and direction of travel of the center of the vehicle, which slips
+    /// towards the inside of the turn
+    pub fn step(&self, heading: Vec2, steering_angle: f32, speed: f32, dt: f32) -> (Vec2, Vec2) {
+        let slip = (0.5 * steering_angle.tan()).atan();
+        let yaw_rate = speed * slip.cos() * steering_angle.tan() / self.wheelbase;
+        let turn = yaw_rate * dt;
+
+        let heading = heading.rotated_by(vec2(turn.cos(), turn.sin()));
+        (heading, heading.rotated_by(vec2(slip.cos(), slip.sin())))
+    }
+}
+
+/// How vehicles of a type turn towards their itinerary
+#[derive(Clone, Copy, Debug)]
+pub enum SteeringModel {
+    /// Rotates the direction towards the objective, with the angular velocity limited by the
+    /// turning radius
+    Direct,
+    Bicycle(Bicycle),
+}
@@ -0,0 +1,180 @@
+use crate::engine_interaction::TimeInfo;
+use crate::map_interaction::Itinerary;
+use crate::physics::{Kinematics, Transform};
+use crate::vehicles::{VehicleComponent, VehicleState, VehicleTypes};
+use geom::Vec2;
+use map_model::{IntersectionID, LaneID, Map, TrafficBehavior, TraverseKind};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+
+/// Speed under which a vehicle at a stop sign is considered stopped, in m/s
+const STOPPED_SPEED: f32 = 0.5;
+
+/// Distance to the control point under which a stopped vehicle joins the queue, in meters
+const QUEUE_DIST: f32 = 10.0;
+
+/// Vehicles arriving within this many seconds of each other are ordered by the right-hand rule
+const TIE_DELAY: f64 = 1.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct StopArrival {
+    pub vehicle: Entity,
+    pub lane: LaneID,
+    pub time: f64,
+    pos: Vec2,
+    dir: Vec2,
+}
+
+impl StopArrival {
+    /// Whether `other` waits on the right of this vehicle
+    fn has_on_right(&self, other: &StopArrival) -> bool {
+        (other.pos - self.pos).dot(self.dir.perpendicular()) > 1.0
+    }
+}
+
+/// Vehicles stopped at the stop signs of an intersection, served one at a time
+#[derive(Default, Debug)]
+pub struct StopQueue {
+    /// Vehicle allowed to enter the intersection and the lane it comes from
+    pub holder: Option<(Entity, LaneID)>,
+    /// At most one vehicle per approach, in arrival order
+    pub arrivals: Vec<StopArrival>,
+}
+
+impl StopQueue {
+    /// First come first served, yielding to the vehicles on the right when arriving at the same time
+    fn next_holder(&self) -> Option<usize> {
+        let first = self.arrivals.first()?.time;
+        let tied = |a: &StopArrival| a.time - first <= TIE_DELAY;
+
+        let free = self.arrivals.iter().enumerate().find(|(_, a)| {
+            tied(a)
+                && !self
+                    .arrivals
+                    .iter()
+                    .any(|b| tied(b) && b.vehicle != a.vehicle && a.has_on_right(b))
+        });
+
+        // Everyone has someone on their right, let the first one go
+        Some(free.map_or(0, |(i, _)| i))
+    }
+}
+
+/// Right-of-way at the intersections with stop signs
+#[derive(Default)]
+pub struct StopSignQueues {
+    pub queues: BTreeMap<IntersectionID, StopQueue>,
+}
+
+impl StopSignQueues {
+    pub fn has_right_of_way(&self, inter: IntersectionID, vehicle: Entity) -> bool {
+        self.queues
+            .get(&inter)
+            .and_then(|q| q.holder)
+            .map_or(false, |(e, _)| e == vehicle)
+    }
+}
+
+pub struct StopSignSystem;
+
+#[derive(SystemData)]
+pub struct StopSignSystemData<'a> {
+    entities: Entities<'a>,
+    map: Read<'a, Map>,
+    time: Read<'a, TimeInfo>,
+    types: Read<'a, VehicleTypes>,
+    queues: Write<'a, StopSignQueues>,
+    transforms: ReadStorage<'a, Transform>,
+    kinematics: ReadStorage<'a, Kinematics>,
+    vehicles: ReadStorage<'a, VehicleComponent>,
+    itinerarys: ReadStorage<'a, Itinerary>,
+}
+
+impl<'a> System<'a> for StopSignSystem {
+    type SystemData = StopSignSystemData<'a>;
+
+    fn run(&mut self, mut data: Self::SystemData) {
+        let map = &*data.map;
+        let time = &*data.time;
+        let queues = &mut data.queues.queues;
+        let itinerarys = &data.itinerarys;
+
+        let lane_of = |e: Entity| match itinerarys.get(e)?.get_travers()?.kind {
+            TraverseKind::Lane(l) => Some(l),
+            TraverseKind::Turn(_) => None,
+        };
+
+        for (ent, trans, kin, vehicle, it) in (
+            &data.entities,
+            &data.transforms,
+            &data.kinematics,
+            &data.vehicles,
+            &data.itinerarys,
+        )
+            .join()
+        {
+            if !matches!(vehicle.state, VehicleState::Driving) || data.types[vehicle.kind].emergency
+            {
+                continue;
+            }
+            let l_id = unwrap_or!(lane_of(ent), continue);
+            let lane = unwrap_or!(map.lanes().get(l_id), continue);
+            if !matches!(
+                lane.control.get_behavior(time.time_seconds),
+                TrafficBehavior::STOP
+            ) {
+                continue;
+            }
+
+            let position = trans.position();
+            if kin.velocity.magnitude() > STOPPED_SPEED
+                || lane.control_point().distance(position) > QUEUE_DIST
+            {
+                continue;
+            }
+
+            let q = queues.entry(lane.dst).or_default();
+            if q.holder.map_or(false, |(_, l)| l == l_id)
+                || q.arrivals.iter().any(|a| a.lane == l_id)
+            {
+                continue;
+            }
+
+            q.arrivals.push(StopArrival {
+                vehicle: ent,
+                lane: l_id,
+                time: time.time,
+                pos: position,
+                dir: trans.direction(),
+            });
+        }
+
+        let entities = &data.entities;
+        for q in queues.values_mut() {
+            // Removed vehicles, or vehicles given another route
+            q.arrivals
+                .retain(|a| entities.is_alive(a.vehicle) && lane_of(a.vehicle) == Some(a.lane));
+
+            // The holder keeps the right-of-way until it has gone through the intersection
+            if let Some((e, l)) = q.holder {
+                let through = !entities.is_alive(e)
+                    || itinerarys.get(e).and_then(Itinerary::get_travers).map_or(
+                        true,
+                        |t| matches!(t.kind, TraverseKind::Lane(cur) if cur != l),
+                    );
+                if through {
+                    q.holder = None;
+                }
+            }
+
+            if q.holder.is_none() {
+                if let Some(i) = q.next_holder() {
+                    let a = q.arrivals.remove(i);
+                    q.holder = Some((a.vehicle, a.lane));
+                }
+            }
+        }
+
+        queues.retain(|_, q| q.holder.is_some() || !q.arrivals.is_empty());
+    }
+}
@@ -1,22 +1,42 @@
 use crate::engine_interaction::TimeInfo;
+use crate::events::SimEvent;
 use crate::frame_log::FrameLog;
-use crate::map_interaction::{Itinerary, ParkingManagement, OBJECTIVE_OK_DIST};
-use crate::physics::{Collider, CollisionWorld, PhysicsGroup, PhysicsObject};
+use crate::map_interaction::{Itinerary, ItineraryKind, ParkingManagement, OBJECTIVE_OK_DIST};
+use crate::pedestrians::CrosswalkOccupancy;
+use crate::physics::{Collider, CollisionWorld, PhysicsObject};
 use crate::physics::{Kinematics, Transform};
+use crate::rand_provider::RandProvider;
 use crate::utils::Restrict;
-use crate::vehicles::{VehicleComponent, VehicleState, DISTANCE2_FOR_UNPARKING, TIME_TO_PARK};
+use crate::vehicles::delivery::route_to;
+use crate::vehicles::{
+    CarFollowing, DeliveryTour, IntersectionReservations, Leader, ODTrip, SteeringModel,
+    StopSignQueues, VehicleComponent, VehicleState, VehicleType, VehicleTypes,
+    DISTANCE2_FOR_UNPARKING, TIME_TO_PARK,
+};
 use geom::intersections::{both_dist_to_inter, Ray};
 use geom::splines::Spline;
-use geom::{angle_lerp, Vec2};
+use geom::{angle_lerp, vec2, Vec2};
 use map_model::{
-    DirectionalPath, LaneKind, Map, ParkingSpotID, TrafficBehavior, Traversable, TraverseDirection,
-    TraverseKind,
+    DirectionalPath, IntersectionID, LaneID, LaneKind, Map, ParkingSpotID, RoadID, TrafficBehavior,
+    Traversable, TraverseDirection, TraverseKind, TurnID, CROSSWALK_WIDTH,
 };
-use rand::thread_rng;
 use specs::prelude::*;
 use specs::shred::PanicHandler;
+use specs::shrev::EventChannel;
 use std::sync::Mutex;
 
+/// Distance under which vehicles yield to an emergency vehicle, in meters
+const EMERGENCY_YIELD_DIST: f32 = 40.0;
+
+/// Speed at which emergency vehicles go through red lights, stop signs and intersections, in m/s
+const EMERGENCY_CAUTION_SPEED: f32 = 5.0;
+
+/// Speed at which vehicles drive while pulling aside for an emergency vehicle, in m/s
+const PULL_ASIDE_SPEED: f32 = 3.0;
+
+/// Distance from the middle of a crosswalk to its edge, in meters
+const CROSSWALK_HALF_WIDTH: f32 = CROSSWALK_WIDTH / 2.0;
+
 #[derive(Default)]
 pub struct VehicleDecision;
 
@@ -25,14 +45,22 @@ pub struct VehicleDecisionSystemData<'a> {
     entities: Entities<'a>,
     map: Read<'a, Map>,
     time: Read<'a, TimeInfo>,
-    parking: Read<'a, ParkingManagement>,
+    parking: Write<'a, ParkingManagement>,
+    types: Read<'a, VehicleTypes>,
+    crosswalks: Read<'a, CrosswalkOccupancy>,
+    stop_queues: Read<'a, StopSignQueues>,
+    reservations: Read<'a, IntersectionReservations>,
     flog: Read<'a, FrameLog>,
     coworld: Write<'a, CollisionWorld, PanicHandler>,
+    events: Write<'a, EventChannel<SimEvent>>,
+    rng: Write<'a, RandProvider, PanicHandler>,
     colliders: WriteStorage<'a, Collider>,
     transforms: WriteStorage<'a, Transform>,
     kinematics: WriteStorage<'a, Kinematics>,
     vehicles: WriteStorage<'a, VehicleComponent>,
     itinerarys: WriteStorage<'a, Itinerary>,
+    od_trips: ReadStorage<'a, ODTrip>,
+    tours: ReadStorage<'a, DeliveryTour>,
 }
 
 impl<'a> System<'a> for VehicleDecision {
@@ -45,10 +73,16 @@ impl<'a> System<'a> for VehicleDecision {
         let map = data.map;
         let time = data.time;
         let parking = data.parking;
+        let types = data.types;
+        let crosswalks = data.crosswalks;
+        let stop_queues = data.stop_queues;
+        let reservations = data.reservations;
 
         {
             let colliders = Mutex::new(&mut data.colliders);
             let cowtex = Mutex::new(&mut *cow);
+            let events = Mutex::new(&mut *data.events);
+            let rng = Mutex::new(&mut *data.rng);
 
             (
                 &data.transforms,
@@ -56,16 +90,41 @@ impl<'a> System<'a> for VehicleDecision {
                 &mut data.vehicles,
                 &mut data.itinerarys,
                 &data.entities,
+                data.od_trips.maybe(),
+                data.tours.maybe(),
             )
                 .par_join()
-                .for_each(|(trans, kin, vehicle, it, ent)| {
+                .for_each(|(trans, kin, vehicle, it, ent, trip, tour)| {
+                    let ty = &types[vehicle.kind];
                     state_update(
-                        vehicle, kin, it, &cowtex, &colliders, ent, &parking, trans, &map, &time,
+                        vehicle,
+                        ty,
+                        kin,
+                        it,
+                        &cowtex,
+                        &colliders,
+                        &events,
+                        &rng,
+                        ent,
+                        &parking,
+                        trans,
+                        &map,
+                        &time,
+                        trip.map(|x| x.destination)
+                            .or_else(|| tour.map(DeliveryTour::destination)),
+                        tour.is_some(),
                     );
                 });
         }
 
+        let emergencies: Vec<EmergencyRoute> = (&data.transforms, &data.vehicles, &data.itinerarys)
+            .join()
+            .filter(|(_, vehicle, _)| types[vehicle.kind].emergency)
+            .map(|(trans, _, it)| EmergencyRoute::new(trans.position(), it, &map))
+            .collect();
+
         (
+            &data.entities,
             &mut data.transforms,
             &mut data.kinematics,
             &mut data.vehicles,
@@ -73,10 +132,10 @@ impl<'a> System<'a> for VehicleDecision {
             &data.colliders,
         )
             .par_join()
-            .for_each(|(trans, kin, vehicle, it, collider)| {
+            .for_each(|(ent, trans, kin, vehicle, it, collider)| {
+                let ty = &types[vehicle.kind];
                 let (_, self_obj) = cow.get(collider.0).expect("Handle not in collision world");
-                let danger_length =
-                    (self_obj.speed.powi(2) / (2.0 * vehicle.kind.deceleration())).min(40.0);
+                let danger_length = (self_obj.speed.powi(2) / (2.0 * ty.deceleration)).min(40.0);
                 let neighbors = cow.query_around(trans.position(), 12.0 + danger_length);
                 let objs = neighbors.map(|(id, pos)| {
                     (
@@ -85,16 +144,37 @@ impl<'a> System<'a> for VehicleDecision {
                     )
                 });
 
-                let (desired_speed, desired_dir) =
-                    calc_decision(vehicle, &map, &time, trans, self_obj, it, objs);
+                let emergency = if ty.emergency {
+                    None
+                } else {
+                    closest_emergency(&emergencies, trans.position(), it, &map)
+                };
+
+                let (desired_speed, desired_dir) = calc_decision(
+                    vehicle,
+                    ty,
+                    &map,
+                    &time,
+                    &crosswalks,
+                    &stop_queues,
+                    &reservations,
+                    ent,
+                    trans,
+                    self_obj,
+                    it,
+                    objs,
+                    emergency,
+                );
 
                 physics(
                     trans,
                     kin,
                     vehicle,
+                    ty,
                     &time,
                     self_obj,
                     &map,
+                    it.local_path(),
                     desired_speed,
                     desired_dir,
                 );
@@ -102,18 +182,25 @@ impl<'a> System<'a> for VehicleDecision {
     }
 }
 
-/// Decides whether a vehicle should change states, from parked to unparking to driving etc
+/// Decides whether a vehicle should change states, from parked to unparking to driving etc.
+/// With `may_double_park`, a vehicle finding no spot near its destination drives there anyway
+/// and stops on the lane.
 fn state_update(
     vehicle: &mut VehicleComponent,
+    ty: &VehicleType,
     kin: &mut Kinematics,
     it: &mut Itinerary,
     cow: &Mutex<&mut CollisionWorld>,
     colliders: &Mutex<&mut WriteStorage<Collider>>,
+    events: &Mutex<&mut EventChannel<SimEvent>>,
+    rng: &Mutex<&mut RandProvider>,
     ent: Entity,
     parking: &ParkingManagement,
     trans: &Transform,
     map: &Map,
     time: &TimeInfo,
+    destination: Option<Vec2>,
+    may_double_park: bool,
 ) {
     match vehicle.state {
         VehicleState::ParkedToRoad => {
@@ -127,6 +214,10 @@ fn state_update(
 
             if distance < DISTANCE2_FOR_UNPARKING {
                 vehicle.state = VehicleState::Driving;
+                events
+                    .lock()
+                    .unwrap()
+                    .single_write(SimEvent::VehicleUnparked(ent));
             }
         }
         VehicleState::RoadToPark(_, ref mut t) => {
@@ -148,10 +239,20 @@ fn state_update(
                 kin.velocity = Vec2::ZERO;
 
                 vehicle.state = VehicleState::Parked(spot);
+                events
+                    .lock()
+                    .unwrap()
+                    .single_write(SimEvent::VehicleParked(ent));
             }
         }
         VehicleState::Driving => {
             if it.has_ended(time.time) {
+                if matches!(it.kind(), ItineraryKind::Simple | ItineraryKind::Route(_)) {
+                    events
+                        .lock()
+                        .unwrap()
+                        .single_write(SimEvent::VehicleArrived(ent));
+                }
                 *it = Itinerary::wait_until(time.time + 20.0);
                 let spot = vehicle.park_spot.and_then(|id| map.parking.get(id));
 
@@ -180,9 +281,23 @@ fn state_update(
                 let travers: Option<Traversable> = lane
                     .map(|x| Traversable::new(TraverseKind::Lane(x), TraverseDirection::Forward));
 
-                if let Some((mut itin, park)) =
-                    next_objective(trans.position(), parking, map, travers.as_ref())
-                {
+                let target = match destination {
+                    Some(dest) => map.closest_lane(dest, LaneKind::Driving).map(|l| (l, dest)),
+                    None => random_target(map, &mut rng.lock().unwrap()),
+                };
+
+                let objective =
+                    next_objective(trans.position(), parking, map, travers.as_ref(), target)
+                        .map(|(itin, park)| (itin, Some(park)))
+                        .or_else(|| {
+                            if !may_double_park {
+                                return None;
+                            }
+                            let itin = route_to(trans.position(), lane?, destination?, map)?;
+                            Some((itin, None))
+                        });
+
+                if let Some((mut itin, park)) = objective {
                     parking.free(spot);
 
                     let points = itin.get_travers().unwrap().points(map); // Unwrap ok: just got itinerary
@@ -204,8 +319,8 @@ fn state_update(
                         trans.position(),
                         PhysicsObject {
                             dir: trans.direction(),
-                            group: PhysicsGroup::Vehicles,
-                            radius: vehicle.kind.width() * 0.5,
+                            group: ty.physics_group(),
+                            radius: ty.width * 0.5,
                             speed: 0.0,
                         },
                     ));
@@ -216,7 +331,7 @@ fn state_update(
                         .expect("Invalid entity ?");
 
                     *it = itin;
-                    vehicle.park_spot = Some(park);
+                    vehicle.park_spot = park;
                     vehicle.state = VehicleState::ParkedToRoad;
                 } else {
                     *it = Itinerary::wait_until(time.time + 10.0);
@@ -227,13 +342,17 @@ fn state_update(
 }
 
 /// Handles actually moving the vehicles around, including acceleration and other physics stuff.
+/// Vehicles using the bicycle model follow `path`, turned by the angle between its start and
+/// `desired_dir`.
 fn physics(
     trans: &mut Transform,
     kin: &mut Kinematics,
     vehicle: &mut VehicleComponent,
+    ty: &VehicleType,
     time: &TimeInfo,
     obj: &PhysicsObject,
     map: &Map,
+    path: &[Vec2],
     desired_speed: f32,
     desired_dir: Vec2,
 ) {
@@ -254,20 +373,34 @@ fn physics(
     }
 
     let speed = obj.speed;
-    let kind = vehicle.kind;
     let direction = trans.direction();
 
     let speed = speed
-        + (desired_speed - speed).restrict(
-            -time.delta * kind.deceleration(),
-            time.delta * kind.acceleration(),
-        );
+        + (desired_speed - speed)
+            .restrict(-time.delta * ty.deceleration, time.delta * ty.acceleration);
+
+    if let SteeringModel::Bicycle(b) = ty.steering {
+        // Pulling aside turns the desired direction away from the path, aim as far beside it
+        let aim = path
+            .first()
+            .and_then(|&p| (p - trans.position()).try_normalize())
+            .map_or(Vec2::UNIT_X, |d| {
+                vec2(d.dot(desired_dir), d.perp_dot(desired_dir))
+            });
+        let target = b.pure_pursuit(trans.position(), direction, speed, path, aim);
+        vehicle.steering_angle = b.turn_wheels(vehicle.steering_angle, target, time.delta);
+
+        let (heading, travel_dir) = b.step(direction, vehicle.steering_angle, speed, time.delta);
+        trans.set_direction(heading);
+        kin.velocity = travel_dir * speed;
+        return;
+    }
 
-    let max_ang_vel = (speed.abs() / kind.min_turning_radius()).restrict(0.0, 2.0);
+    let max_ang_vel = (speed.abs() / ty.min_turning_radius).restrict(0.0, 2.0);
 
     let approx_angle = direction.distance(desired_dir);
 
-    vehicle.ang_velocity += time.delta * kind.ang_acc();
+    vehicle.ang_velocity += time.delta * ty.ang_acc;
     vehicle.ang_velocity = vehicle
         .ang_velocity
         .min(3.0 * approx_angle)
@@ -282,47 +415,196 @@ fn physics(
     kin.velocity = trans.direction() * speed;
 }
 
-fn next_objective(
+pub(crate) fn next_objective(
     pos: Vec2,
     parking: &ParkingManagement,
     map: &Map,
     last_travers: Option<&Traversable>,
+    target: Option<(LaneID, Vec2)>,
 ) -> Option<(Itinerary, ParkingSpotID)> {
-    let rlane = map.get_random_lane(LaneKind::Driving, &mut thread_rng())?;
-    let spot_id = parking.reserve_near(
+    let last_travers = *last_travers.filter(|t| t.is_valid(map))?;
+    let (spot_id, obj) = objective_spot(parking, map, target?)?;
+
+    match Itinerary::route(pos, last_travers, obj, map, &DirectionalPath) {
+        Some(it) => Some((it, spot_id)),
+        None => {
+            parking.free(spot_id);
+            None
+        }
+    }
+}
+
+/// Like `next_objective`, but leaving the lane `cur` through the turn `via`
+pub(crate) fn detour_objective(
+    pos: Vec2,
+    parking: &ParkingManagement,
+    map: &Map,
+    cur: Traversable,
+    via: TurnID,
+    target: (LaneID, Vec2),
+) -> Option<(Itinerary, ParkingSpotID)> {
+    let (spot_id, obj) = objective_spot(parking, map, target)?;
+
+    match Itinerary::route_via(pos, cur, via, obj, map, &DirectionalPath) {
+        Some(it) => Some((it, spot_id)),
+        None => {
+            parking.free(spot_id);
+            None
+        }
+    }
+}
+
+/// Picks a random point on a random driving lane, to wander to when there is no destination
+pub(crate) fn random_target(map: &Map, rng: &mut RandProvider) -> Option<(LaneID, Vec2)> {
+    let rlane = map.get_random_lane(LaneKind::Driving, &mut rng.rng)?;
+    Some((
         rlane.id,
         rlane
             .points
-            .point_along(rand::random::<f32>() * rlane.points.length()),
-        map,
-    )?;
+            .point_along(rng.random::<f32>() * rlane.points.length()),
+    ))
+}
+
+/// Reserves a parking spot near the target point of the given lane.
+/// Returns the spot and the point to drive to on its lane.
+fn objective_spot(
+    parking: &ParkingManagement,
+    map: &Map,
+    (lane, near): (LaneID, Vec2),
+) -> Option<(ParkingSpotID, (LaneID, Vec2))> {
+    let spot_id = parking.reserve_near(lane, near, map)?;
 
-    let l = &map.lanes()[map.parking_to_drive(spot_id)?];
+    let l = unwrap_or!(map.parking_to_drive(spot_id), {
+        parking.free(spot_id);
+        return None;
+    });
+    let l = &map.lanes()[l];
 
     let spot = map.parking.get(spot_id).unwrap(); // Unwrap ok: gotten using reserve_near
 
     let p = l.points.project(spot.pos);
     let dist = l.points.distance_along(p);
 
-    Itinerary::route(
-        pos,
-        *last_travers.filter(|t| t.is_valid(map))?,
-        (l.id, l.points.point_along(dist - 5.0)),
-        map,
-        &DirectionalPath,
-    )
-    .map(move |it| (it, spot_id))
+    Some((spot_id, (l.id, l.points.point_along(dist - 5.0))))
+}
+
+/// Where an emergency vehicle is going
+struct EmergencyRoute {
+    pos: Vec2,
+    road: Option<RoadID>,
+    /// Intersections of its current and next traversables
+    intersections: Vec<IntersectionID>,
+}
+
+impl EmergencyRoute {
+    fn new(pos: Vec2, it: &Itinerary, map: &Map) -> Self {
+        let road = it.get_travers().and_then(|t| road_of(t, map));
+        let intersections = it
+            .get_travers()
+            .into_iter()
+            .chain(it.peek_next())
+            .filter_map(|t| intersection_of(t, map))
+            .collect();
+
+        Self {
+            pos,
+            road,
+            intersections,
+        }
+    }
+}
+
+/// Road of the lane, none for turns
+fn road_of(t: &Traversable, map: &Map) -> Option<RoadID> {
+    match t.kind {
+        TraverseKind::Lane(id) => map.lanes().get(id).map(|l| l.parent),
+        TraverseKind::Turn(_) => None,
+    }
+}
+
+/// Intersection at the end of the lane, or of the turn
+fn intersection_of(t: &Traversable, map: &Map) -> Option<IntersectionID> {
+    match t.kind {
+        TraverseKind::Lane(id) => map.lanes().get(id).map(|l| l.dst),
+        TraverseKind::Turn(id) => Some(id.parent),
+    }
+}
+
+/// Position of the closest emergency vehicle that should be yielded to: one going through the
+/// same intersection, or driving on the same road
+fn closest_emergency(
+    emergencies: &[EmergencyRoute],
+    position: Vec2,
+    it: &Itinerary,
+    map: &Map,
+) -> Option<Vec2> {
+    let travers = it.get_travers()?;
+    let road = road_of(travers, map);
+    let inter = intersection_of(travers, map);
+
+    emergencies
+        .iter()
+        .filter(|e| e.pos.distance2(position) < EMERGENCY_YIELD_DIST * EMERGENCY_YIELD_DIST)
+        .filter(|e| {
+            (road.is_some() && e.road == road)
+                || inter.map_or(false, |i| e.intersections.contains(&i))
+        })
+        .map(|e| e.pos)
+        .min_by(|a, b| {
+            a.distance2(position)
+                .partial_cmp(&b.distance2(position))
+                .unwrap()
+        })
+}
+
+/// Distance along the itinerary to the next crosswalk with pedestrians on it or about to step on it,
+/// looking as far as the end of the next turn
+fn busy_crosswalk_dist(
+    crosswalks: &CrosswalkOccupancy,
+    map: &Map,
+    position: Vec2,
+    it: &Itinerary,
+) -> Option<f32> {
+    if crosswalks.is_empty() {
+        return None;
+    }
+
+    let mut path = vec![position];
+    path.extend_from_slice(it.local_path());
+
+    let turn = match it.get_travers()?.kind {
+        TraverseKind::Turn(id) => id,
+        TraverseKind::Lane(_) => match it.peek_next()?.kind {
+            TraverseKind::Turn(id) => {
+                let turn = map.intersections().get(id.parent)?.find_turn(id)?;
+                path.extend_from_slice(turn.points.as_slice());
+                id
+            }
+            TraverseKind::Lane(_) => return None,
+        },
+    };
+
+    crosswalks
+        .first_busy_crossing(map, turn, &path)
+        .map(|(_, dist)| dist)
 }
 
 /// Decide the appropriate velocity and direction to aim for.
+/// `emergency` is the position of a nearby emergency vehicle to yield to.
 pub fn calc_decision<'a>(
     vehicle: &mut VehicleComponent,
+    ty: &VehicleType,
     map: &Map,
     time: &TimeInfo,
+    crosswalks: &CrosswalkOccupancy,
+    stop_queues: &StopSignQueues,
+    reservations: &IntersectionReservations,
+    ent: Entity,
     trans: &Transform,
     self_obj: &PhysicsObject,
     it: &Itinerary,
     neighs: impl Iterator<Item = (Vec2, &'a PhysicsObject)>,
+    emergency: Option<Vec2>,
 ) -> (f32, Vec2) {
     let default_return = (0.0, self_obj.dir);
     if vehicle.wait_time > 0.0 {
@@ -333,11 +615,11 @@ pub fn calc_decision<'a>(
 
     let terminal_pos = it.get_terminal();
 
-    let front_dist = calc_front_dist(vehicle, trans, self_obj, it, neighs);
+    let leader = calc_leader(ty, trans, self_obj, it, neighs);
 
     let position = trans.position();
     let speed = self_obj.speed;
-    if speed.abs() < 0.2 && front_dist < 1.5 {
+    if speed.abs() < 0.2 && leader.map_or(false, |l| l.gap < 1.5) {
         vehicle.wait_time = (position.x * 1000.0).fract().abs() * 0.5;
         return default_return;
     }
@@ -349,10 +631,10 @@ pub fn calc_decision<'a>(
 
     // If unparking, just set desired speed to 1/5 of usual
     if let VehicleState::ParkedToRoad = vehicle.state {
-        return (vehicle.kind.cruising_speed() / 5.0, dir_to_pos);
+        return (ty.cruising_speed / 5.0, dir_to_pos);
     }
 
-    let time_to_stop = speed / vehicle.kind.deceleration();
+    let time_to_stop = speed / ty.deceleration;
     let stop_dist = time_to_stop * speed * 0.5;
 
     if let Some(pos) = terminal_pos {
@@ -362,64 +644,122 @@ pub fn calc_decision<'a>(
         }
     }
 
-    if let Some(Traversable {
-        kind: TraverseKind::Lane(l_id),
-        ..
-    }) = it.get_travers()
-    {
-        if let Some(l) = map.lanes().get(*l_id) {
-            let dist_to_light = l.control_point().distance(position);
-            match l.control.get_behavior(time.time_seconds) {
-                TrafficBehavior::RED | TrafficBehavior::ORANGE => {
-                    if dist_to_light
-                        < OBJECTIVE_OK_DIST * 1.05
-                            + 2.0
-                            + stop_dist
-                            + (vehicle.kind.width() * 0.5 - OBJECTIVE_OK_DIST).max(0.0)
-                    {
-                        return (0.0, dir_to_pos);
+    // Yield to pedestrians, unless already on the crosswalk
+    if let Some(dist) = busy_crosswalk_dist(crosswalks, map, position, it) {
+        let front_dist = dist - ty.width * 0.5;
+        if front_dist > 0.0 && front_dist < CROSSWALK_HALF_WIDTH + 1.0 + stop_dist {
+            return (0.0, dir_to_pos);
+        }
+    }
+
+    // Not facing the objective
+    let mut desired_speed = if dir_to_pos.dot(trans.direction()) < 0.8 {
+        6.0
+    } else {
+        ty.cruising_speed
+    };
+    let mut desired_dir = dir_to_pos;
+
+    match it.get_travers().map(|t| t.kind) {
+        Some(TraverseKind::Lane(l_id)) => {
+            if let Some(l) = map.lanes().get(l_id) {
+                let dist_to_light = l.control_point().distance(position);
+                let stop_line = OBJECTIVE_OK_DIST * 1.05
+                    + 2.0
+                    + stop_dist
+                    + (ty.width * 0.5 - OBJECTIVE_OK_DIST).max(0.0);
+
+                let behavior = l.control.get_behavior(time.time_seconds);
+                let must_stop = match behavior {
+                    TrafficBehavior::RED | TrafficBehavior::ORANGE => dist_to_light < stop_line,
+                    TrafficBehavior::STOP => {
+                        !stop_queues.has_right_of_way(l.dst, ent) && dist_to_light < stop_line
+                    }
+                    TrafficBehavior::RESERVATION => {
+                        let early = reservations
+                            .get(l.dst, ent)
+                            .map_or(true, |r| r.enter - time.time > 0.5);
+                        early && dist_to_light < stop_line
+                    }
+                    _ => false,
+                };
+
+                if let (TrafficBehavior::RESERVATION, Some(r)) =
+                    (behavior, reservations.get(l.dst, ent))
+                {
+                    // Arrive at the intersection when the slot starts
+                    let time_left = (r.enter - time.time) as f32;
+                    if time_left > 0.0 {
+                        desired_speed = desired_speed.min(dist_to_light / time_left);
                     }
                 }
-                TrafficBehavior::STOP => {
-                    if dist_to_light < OBJECTIVE_OK_DIST * 0.95 + stop_dist {
+
+                if ty.emergency {
+                    // Slow down enough before the intersection to go through it carefully
+                    let caution_dist = (speed.powi(2) - EMERGENCY_CAUTION_SPEED.powi(2)).max(0.0)
+                        / (2.0 * ty.deceleration)
+                        + stop_line;
+                    let controlled = matches!(
+                        behavior,
+                        TrafficBehavior::RED
+                            | TrafficBehavior::ORANGE
+                            | TrafficBehavior::STOP
+                            | TrafficBehavior::RESERVATION
+                    );
+                    if controlled && dist_to_light < caution_dist {
+                        desired_speed = desired_speed.min(EMERGENCY_CAUTION_SPEED);
+                    }
+                } else if must_stop {
+                    return (0.0, dir_to_pos);
+                } else if let Some(epos) = emergency {
+                    // Hold at the intersection to let the emergency vehicle through
+                    if dist_to_light < stop_line {
                         return (0.0, dir_to_pos);
                     }
+
+                    // Pull aside to the right if it comes from behind
+                    if (epos - position).dot(trans.direction()) < 0.0 {
+                        desired_speed = desired_speed.min(PULL_ASIDE_SPEED);
+                        desired_dir = (dir_to_pos + dir_to_pos.perpendicular() * 0.25).normalize();
+                    }
                 }
-                _ => {}
             }
         }
+        Some(TraverseKind::Turn(_)) if ty.emergency => {
+            desired_speed = desired_speed.min(EMERGENCY_CAUTION_SPEED);
+        }
+        _ => {}
     }
 
-    // Stop at 80 cm of object in front
-    if front_dist < 0.8 + stop_dist {
-        return (0.0, dir_to_pos);
-    }
+    let target_speed = ty
+        .car_following
+        .target_speed(ty, speed, desired_speed, leader, time.delta);
 
-    // Not facing the objective
-    if dir_to_pos.dot(trans.direction()) < 0.8 {
-        return (6.0, dir_to_pos);
-    }
-
-    (vehicle.kind.cruising_speed(), dir_to_pos)
+    (target_speed, desired_dir)
 }
 
-/// Calculates the distance to the closest problematic object in front of the car.
+/// Finds the closest problematic object in front of the car.
 /// It can be another car or a pedestrian, or it can be a potential collision point from a
 /// car coming perpendicularly.
-fn calc_front_dist<'a>(
-    vehicle: &mut VehicleComponent,
+fn calc_leader<'a>(
+    ty: &VehicleType,
     trans: &Transform,
     self_obj: &PhysicsObject,
     it: &Itinerary,
     neighs: impl Iterator<Item = (Vec2, &'a PhysicsObject)>,
-) -> f32 {
+) -> Option<Leader> {
     let position = trans.position();
     let direction = trans.direction();
 
-    let mut min_front_dist: f32 = 50.0;
+    let mut leader: Option<Leader> = None;
+    let mut consider = |gap: f32, speed: f32| {
+        if leader.map_or(gap < 50.0, |l| gap < l.gap) {
+            leader = Some(Leader { gap, speed });
+        }
+    };
 
     let my_ray = Ray {
-        from: position - direction * vehicle.kind.width() * 0.5,
+        from: position - direction * ty.width * 0.5,
         dir: direction,
     };
 
@@ -448,7 +788,7 @@ fn calc_front_dist<'a>(
 
         let dist_to_side = towards_vec.perp_dot(direction).abs();
 
-        let is_vehicle = matches!(nei_physics_obj.group, PhysicsGroup::Vehicles);
+        let is_vehicle = nei_physics_obj.group.is_vehicle();
 
         let cos_direction_angle = nei_physics_obj.dir.dot(direction);
 
@@ -458,10 +798,13 @@ fn calc_front_dist<'a>(
             && (!on_lane || dist_to_side < 3.0)
         {
             let mut dist_to_obj = dist - my_radius - nei_physics_obj.radius;
-            if !is_vehicle {
+            let mut his_speed = 0.0;
+            if is_vehicle {
+                his_speed = nei_physics_obj.speed * cos_direction_angle;
+            } else {
                 dist_to_obj -= 1.0;
             }
-            min_front_dist = min_front_dist.min(dist_to_obj);
+            consider(dist_to_obj, his_speed);
             continue;
         }
 
@@ -484,7 +827,7 @@ fn calc_front_dist<'a>(
             continue;
         }
 
-        min_front_dist = min_front_dist.min(dist - my_radius - nei_physics_obj.radius - 5.0);
+        consider(dist - my_radius - nei_physics_obj.radius - 5.0, 0.0);
     }
-    min_front_dist
+    leader
 }
@@ -0,0 +1,344 @@
+use crate::physics::PhysicsGroup;
+use crate::rendering::assets::{AssetID, AssetRegistry};
+use crate::rendering::Color;
+use crate::vehicles::{Bicycle, CarFollowingModel, Idm, Rules, SteeringModel};
+use mods::mlua;
+use mods::mlua::{Table, Value};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::Index;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Index of a vehicle type in the `VehicleTypes` resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VehicleKind(pub u16);
+
+debug_inspect_impl!(VehicleKind);
+
+#[derive(Clone, Debug)]
+pub struct VehicleType {
+    pub name: String,
+    /// Length along the direction of travel, in meters
+    pub width: f32,
+    pub height: f32,
+    pub mass: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    pub min_turning_radius: f32,
+    pub cruising_speed: f32,
+    pub ang_acc: f32,
+    pub car_following: CarFollowingModel,
+    pub steering: SteeringModel,
+    /// Goes through red lights and stop signs with caution, other vehicles yield to it
+    pub emergency: bool,
+
+    pub sprite: String,
+    pub asset: AssetID,
+    pub scale: f32,
+    /// Colours and their relative frequency
+    pub colors: Vec<(Color, f32)>,
+    /// Relative frequency of the type when spawning random vehicles
+    pub spawn_weight: f32,
+}
+
+impl VehicleType {
+    pub fn car() -> Self {
+        Self {
+            name: "car".to_string(),
+            width: 4.5,
+            height: 2.0,
+            mass: 1000.0,
+            acceleration: 3.0,
+            deceleration: 9.0,
+            min_turning_radius: 3.0,
+            cruising_speed: 15.0,
+            ang_acc: 1.0,
+            car_following: CarFollowingModel::Rules(Rules),
+            steering: SteeringModel::Direct,
+            emergency: false,
+            sprite: "resources/car.png".to_string(),
+            asset: AssetID::CAR,
+            scale: 4.5,
+            colors: vec![
+                (Color::from_hex(0x22_22_22), 0.22),  // Black
+                (Color::from_hex(0xff_ff_ff), 0.19),  // White
+                (Color::from_hex(0x66_66_66), 0.17),  // Gray
+                (Color::from_hex(0xb8_b8_b8), 0.14),  // Silver
+                (Color::from_hex(0x1a_3c_70), 0.1),   // Blue
+                (Color::from_hex(0xd8_22_00), 0.1),   // Red
+                (Color::from_hex(0x7c_4b_24), 0.02),  // Brown
+                (Color::from_hex(0xd4_c6_78), 0.015), // Gold
+                (Color::from_hex(0x72_cb_19), 0.015), // Green
+            ],
+            spawn_weight: 1.0,
+        }
+    }
+
+    pub fn bus() -> Self {
+        Self {
+            name: "bus".to_string(),
+            width: 9.0,
+            height: 2.0,
+            mass: 10000.0,
+            acceleration: 2.0,
+            deceleration: 9.0,
+            min_turning_radius: 5.0,
+            cruising_speed: 10.0,
+            ang_acc: 0.8,
+            car_following: CarFollowingModel::Idm(Idm::BUS),
+            scale: 9.0,
+            colors: vec![(Color::from_hex(0xd8_22_00), 1.0)],
+            spawn_weight: 0.0,
+            ..Self::car()
+        }
+    }
+
+    pub fn physics_group(&self) -> PhysicsGroup {
+        if self.emergency {
+            PhysicsGroup::EmergencyVehicles
+        } else {
+            PhysicsGroup::Vehicles
+        }
+    }
+
+    pub fn random_color(&self, rng: &mut impl Rng) -> Color {
+        let total: f32 = self.colors.iter().map(|x| x.1).sum();
+
+        let r = rng.gen::<f32>() * total;
+        let mut partial = 0.0;
+        for (col, freq) in &self.colors {
+            partial += freq;
+            if partial >= r {
+                return *col;
+            }
+        }
+        Color::WHITE
+    }
+
+    /// Reads a type from a lua table, missing fields are taken from `base`
+    fn from_lua(t: &Table, base: &VehicleType) -> mlua::Result<VehicleType> {
+        let f = |key: &str, default: f32| -> mlua::Result<f32> {
+            Ok(t.get::<_, Option<f32>>(key)?.unwrap_or(default))
+        };
+
+        let colors = match t.get::<_, Option<Table>>("colors")? {
+            Some(colors) => colors
+                .sequence_values::<Table>()
+                .map(|c| {
+                    let c = c?;
+                    Ok((Color::from_hex(c.get(1)?), c.get(2)?))
+                })
+                .collect::<mlua::Result<Vec<_>>>()?,
+            None => base.colors.clone(),
+        };
+
+        let car_following = match t.get::<_, Value>("car_following")? {
+            Value::Nil => base.car_following,
+            Value::String(s) => match s.to_str()? {
+                "rules" => CarFollowingModel::Rules(Rules),
+                "idm" => CarFollowingModel::Idm(Idm::CAR),
+                x => {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "unknown car following model `{}`",
+                        x
+                    )))
+                }
+            },
+            Value::Table(cf) => {
+                let ff = |key: &str, default: f32| -> mlua::Result<f32> {
+                    Ok(cf.get::<_, Option<f32>>(key)?.unwrap_or(default))
+                };
+                let d = Idm::CAR;
+                CarFollowingModel::Idm(Idm {
+                    time_headway: ff("time_headway", d.time_headway)?,
+                    min_gap: ff("min_gap", d.min_gap)?,
+                    max_acceleration: ff("max_acceleration", d.max_acceleration)?,
+                    comfortable_deceleration: ff(
+                        "comfortable_deceleration",
+                        d.comfortable_deceleration,
+                    )?,
+                    delta: ff("delta", d.delta)?,
+                })
+            }
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "car_following should be a string or a table".to_string(),
+                ))
+            }
+        };
+
+        let width = f("width", base.width)?;
+        let steering = match t.get::<_, Value>("steering")? {
+            Value::Nil => base.steering,
+            Value::String(s) => match s.to_str()? {
+                "direct" => SteeringModel::Direct,
+                "bicycle" => SteeringModel::Bicycle(Bicycle::with_length(width)),
+                x => {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "unknown steering model `{}`",
+                        x
+                    )))
+                }
+            },
+            Value::Table(st) => {
+                let ff = |key: &str, default: f32| -> mlua::Result<f32> {
+                    Ok(st.get::<_, Option<f32>>(key)?.unwrap_or(default))
+                };
+                let d = Bicycle::with_length(width);
+                SteeringModel::Bicycle(Bicycle {
+                    wheelbase: ff("wheelbase", d.wheelbase)?,
+                    max_steering_angle: ff("max_steering_angle", d.max_steering_angle)?,
+                    steering_rate: ff("steering_rate", d.steering_rate)?,
+                    lookahead_time: ff("lookahead_time", d.lookahead_time)?,
+                    min_lookahead: ff("min_lookahead", d.min_lookahead)?,
+                })
+            }
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "steering should be a string or a table".to_string(),
+                ))
+            }
+        };
+
+        Ok(VehicleType {
+            name: t.get("name")?,
+            width,
+            height: f("height", base.height)?,
+            mass: f("mass", base.mass)?,
+            acceleration: f("acceleration", base.acceleration)?,
+            deceleration: f("deceleration", base.deceleration)?,
+            min_turning_radius: f("min_turning_radius", base.min_turning_radius)?,
+            cruising_speed: f("cruising_speed", base.cruising_speed)?,
+            ang_acc: f("ang_acc", base.ang_acc)?,
+            car_following,
+            steering,
+            emergency: t
+                .get::<_, Option<bool>>("emergency")?
+                .unwrap_or(base.emergency),
+            sprite: t
+                .get::<_, Option<String>>("sprite")?
+                .unwrap_or_else(|| base.sprite.clone()),
+            asset: base.asset,
+            scale: f("scale", base.scale)?,
+            colors,
+            spawn_weight: f("spawn_weight", base.spawn_weight)?,
+        })
+    }
+}
+
+/// Every vehicle type, usually loaded from lua/vehicles.lua and the mods.
+/// There is always at least one type.
+pub struct VehicleTypes {
+    types: Vec<VehicleType>,
+}
+
+impl Default for VehicleTypes {
+    fn default() -> Self {
+        Self {
+            types: vec![VehicleType::car(), VehicleType::bus()],
+        }
+    }
+}
+
+/// Falls back to the first type for kinds that aren't loaded, e.g. from a save made with other
+/// mods
+impl Index<VehicleKind> for VehicleTypes {
+    type Output = VehicleType;
+
+    fn index(&self, kind: VehicleKind) -> &Self::Output {
+        self.get(kind).unwrap_or(&self.types[0])
+    }
+}
+
+impl VehicleTypes {
+    /// Evaluates the given scripts in order, where each call to `vehicle_type { ... }` declares
+    /// a type, replacing an earlier one of the same name.
+    /// Falls back to the built-in types if the scripts fail or declare nothing.
+    pub fn load<P: AsRef<Path>>(paths: &[P], assets: &mut AssetRegistry) -> Self {
+        let declared: Arc<Mutex<Vec<VehicleType>>> = Arc::new(Mutex::new(vec![]));
+
+        for path in paths {
+            let decl = declared.clone();
+            let lua = mods::load_with(path, move |lua| {
+                let f = lua.create_function(move |_, t: Table| {
+                    let ty = VehicleType::from_lua(&t, &VehicleType::car())?;
+                    let mut decl = decl.lock().unwrap();
+                    match decl.iter_mut().find(|x| x.name == ty.name) {
+                        Some(x) => *x = ty,
+                        None => decl.push(ty),
+                    }
+                    Ok(())
+                });
+                match f {
+                    Ok(f) => {
+                        if let Err(e) = lua.globals().set("vehicle_type", f) {
+                            error!("{}", e);
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            });
+            if lua.is_none() {
+                warn!("could not load vehicle types from {:?}", path.as_ref());
+            }
+        }
+
+        let types = std::mem::take(&mut *declared.lock().unwrap());
+        if types.is_empty() {
+            warn!("no vehicle types loaded, using the defaults");
+            return Self::default();
+        }
+
+        let types = types
+            .into_iter()
+            .map(|mut ty| {
+                ty.asset = assets.register(&ty.sprite);
+                ty
+            })
+            .collect();
+
+        Self { types }
+    }
+
+    pub fn get(&self, kind: VehicleKind) -> Option<&VehicleType> {
+        self.types.get(kind.0 as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (VehicleKind, &VehicleType)> {
+        self.types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (VehicleKind(i as u16), ty))
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<VehicleKind> {
+        self.iter().find(|(_, ty)| ty.name == name).map(|x| x.0)
+    }
+
+    /// First type marked as emergency
+    pub fn emergency_kind(&self) -> Option<VehicleKind> {
+        self.iter().find(|(_, ty)| ty.emergency).map(|x| x.0)
+    }
+
+    /// Random type according to the spawn weights
+    pub fn random_kind(&self, rng: &mut impl Rng) -> Option<VehicleKind> {
+        let total: f32 = self.types.iter().map(|x| x.spawn_weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let r = rng.gen::<f32>() * total;
+        let mut partial = 0.0;
+        for (kind, ty) in self.iter() {
+            partial += ty.spawn_weight;
+            if partial >= r && ty.spawn_weight > 0.0 {
+                return Some(kind);
+            }
+        }
+        self.iter()
+            .filter(|(_, ty)| ty.spawn_weight > 0.0)
+            .last()
+            .map(|x| x.0)
+    }
+}
@@ -140,6 +140,18 @@ impl PolyLine {
         }
     }
 
+    /// First point, along self, where the two polylines cross
+    pub fn intersection(&self, other: &PolyLine) -> Option<Vec2> {
+        self.0.windows(2).find_map(|w| {
+            let s = Segment::new(w[0], w[1]);
+            other
+                .0
+                .windows(2)
+                .filter_map(|w2| s.intersection(&Segment::new(w2[0], w2[1])))
+                .min_by_key(|p| OrderedFloat(p.distance2(w[0])))
+        })
+    }
+
     pub fn segment_vec(&self, id: usize) -> Option<Vec2> {
         Some(self.get(id + 1)? - self.get(id)?)
     }
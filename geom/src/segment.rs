@@ -27,4 +27,28 @@ impl Segment {
             self.src + diff * lol
         }
     }
+
+    /// Point where the two segments cross, if any.
+    /// Segments touching at an end are considered crossing, with some tolerance for rounding errors
+    pub fn intersection(&self, other: &Segment) -> Option<Vec2> {
+        let r = self.dst - self.src;
+        let s = other.dst - other.src;
+
+        let div = r.perp_dot(s);
+        if div.abs() < std::f32::EPSILON {
+            return None;
+        }
+
+        let p_diff = other.src - self.src;
+        let t = p_diff.perp_dot(s) / div;
+        let u = p_diff.perp_dot(r) / div;
+
+        const EPS: f32 = 1e-3;
+        let range = -EPS..=1.0 + EPS;
+        if range.contains(&t) && range.contains(&u) {
+            Some(self.src + r * t)
+        } else {
+            None
+        }
+    }
 }
@@ -1,17 +1,137 @@
 use argh::FromArgs;
 use egregoria::engine_interaction::TimeInfo;
-use egregoria::specs::WorldExt;
-use egregoria::EgregoriaState;
+use egregoria::lua::LuaCallbacks;
+use egregoria::physics::{Kinematics, Transform};
+use egregoria::specs::rayon::prelude::*;
+use egregoria::specs::rayon::ThreadPoolBuilder;
+use egregoria::specs::{Join, World, WorldExt};
+use egregoria::stats::{IncidentKind, SafetyLog, TrafficStats};
+use egregoria::vehicles::ODMatrix;
+use egregoria::{DispatchMode, EgregoriaState, RandProvider};
+use geom::Vec2;
 use log::LevelFilter;
-use std::path::Path;
+use map_model::Map;
+use mods::mlua::{self, Lua};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
 #[derive(FromArgs)]
 #[argh(description = "\
 Egregoria's headless cli for running egregoria scenarios.\n\
-Example: goria test.lua")]
+Example: goria test.lua\n\
+Example: goria lua/scenarios -p cars=2,4,8 -p spacing=5,10 -o results.csv")]
 struct Args {
     #[argh(positional)]
     scenario: Vec<String>,
+
+    /// parameter to sweep, as name=v1,v2,... Can be repeated, every combination is run.
+    /// Values are available to the scenario through the `params` global table.
+    #[argh(option, short = 'p')]
+    param: Vec<String>,
+
+    /// csv file where the results of all runs are written
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// number of steps after which a run is considered a failure
+    #[argh(option, default = "1000")]
+    max_steps: u32,
+
+    /// directory containing zones.csv and matrix.csv, used to generate trips once the
+    /// scenario is initialized
+    #[argh(option)]
+    od: Option<String>,
+
+    /// directory where the traffic statistics of each run are exported as csv
+    #[argh(option)]
+    stats: Option<String>,
+
+    /// length in seconds of the traffic statistics aggregation intervals
+    #[argh(option, default = "60.0")]
+    stats_interval: f32,
+
+    /// run the systems of each simulation one after the other on a single thread, with
+    /// randomness drawn from the world's seeded generator. Scenarios are still run in
+    /// parallel. Use --check-determinism to verify that the runs are reproducible.
+    #[argh(switch)]
+    sequential: bool,
+
+    /// run every scenario a second time and report the runs whose results differ.
+    /// Implies --sequential.
+    #[argh(switch)]
+    check_determinism: bool,
+}
+
+#[derive(Clone, Copy)]
+enum RunStatus {
+    Success,
+    Failure,
+    Error,
+}
+
+impl AsRef<str> for RunStatus {
+    fn as_ref(&self) -> &str {
+        match self {
+            RunStatus::Success => "success",
+            RunStatus::Failure => "failure",
+            RunStatus::Error => "error",
+        }
+    }
+}
+
+struct RunConfig {
+    max_steps: u32,
+    dispatch: DispatchMode,
+    od: Option<PathBuf>,
+    stats: Option<StatsExport>,
+}
+
+struct StatsExport {
+    dir: PathBuf,
+    interval: f32,
+}
+
+impl StatsExport {
+    /// Each run gets its own directory, named after the scenario and the parameters
+    fn run_dir(&self, scenario: &Path, params: &[(String, f64)]) -> PathBuf {
+        let mut name = scenario
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for (k, v) in params {
+            name.push_str(&format!("_{}={}", k, v));
+        }
+        self.dir.join(name)
+    }
+}
+
+struct RunResult {
+    scenario: PathBuf,
+    params: Vec<(String, f64)>,
+    status: RunStatus,
+    steps: u32,
+    sim_time: f64,
+    wall_time: f32,
+    collisions: usize,
+    near_misses: usize,
+    /// Hash of the final state of the simulation, see `state_digest`
+    digest: u64,
+}
+
+impl RunResult {
+    /// Whether two runs ended in the same state, regardless of how long they took
+    fn same_outcome(&self, other: &RunResult) -> bool {
+        self.status.as_ref() == other.status.as_ref()
+            && self.steps == other.steps
+            && self.collisions == other.collisions
+            && self.near_misses == other.near_misses
+            && self.digest == other.digest
+    }
 }
 
 fn main() {
@@ -22,48 +142,390 @@ fn main() {
 
     let args: Args = argh::from_env();
 
+    let params = unwrap_or_exit(parse_params(&args.param));
+
+    let mut scenarios = vec![];
     for scenario in args.scenario {
         if let Ok(r) = std::fs::read_dir(&scenario) {
-            for p in r.filter_map(|x| x.ok()) {
-                let state = egregoria::EgregoriaState::init();
-                run(state, p.path().as_path())
-            }
+            scenarios.extend(r.filter_map(|x| x.ok()).map(|x| x.path()));
         } else {
-            let state = egregoria::EgregoriaState::init();
-            run(state, scenario.as_str().as_ref())
+            scenarios.push(PathBuf::from(scenario));
         }
     }
+
+    let combinations = param_grid(&params);
+
+    let jobs: Vec<(&Path, &[(String, f64)])> = scenarios
+        .iter()
+        .flat_map(|s| {
+            combinations
+                .iter()
+                .map(move |p| (s.as_path(), p.as_slice()))
+        })
+        .collect();
+
+    log::info!(
+        "running {} scenario(s) with {} parameter combination(s)",
+        scenarios.len(),
+        combinations.len()
+    );
+
+    // Runs and their systems share one pool instead of each run starting a thread per core
+    let pool = Arc::new(unwrap_or_exit(
+        ThreadPoolBuilder::new()
+            .build()
+            .map_err(|e| format!("could not create the thread pool: {}", e)),
+    ));
+
+    let config = RunConfig {
+        max_steps: args.max_steps,
+        dispatch: if args.sequential || args.check_determinism {
+            DispatchMode::Sequential
+        } else {
+            DispatchMode::Shared(pool.clone())
+        },
+        od: args.od.map(PathBuf::from),
+        stats: args.stats.map(|dir| StatsExport {
+            dir: PathBuf::from(dir),
+            interval: args.stats_interval,
+        }),
+    };
+
+    // The second runs don't export their statistics, they would overwrite the first ones
+    let check = args.check_determinism;
+    let check_config = RunConfig {
+        max_steps: config.max_steps,
+        dispatch: config.dispatch.clone(),
+        od: config.od.clone(),
+        stats: None,
+    };
+
+    let runs: Vec<(RunResult, Option<RunResult>)> = pool.install(|| {
+        jobs.into_par_iter()
+            .map(|(scenario, params)| {
+                let first = run(scenario, params, &config);
+                let second = if check {
+                    Some(run(scenario, params, &check_config))
+                } else {
+                    None
+                };
+                (first, second)
+            })
+            .collect()
+    });
+
+    let mut mismatches = 0;
+    for (first, second) in &runs {
+        if let Some(second) = second {
+            if !first.same_outcome(second) {
+                mismatches += 1;
+                log::error!(
+                    "{:?} {:?} is not deterministic: {} after {} steps with {} collision(s), \
+                     {} near miss(es) and state {:016x}, then {} after {} steps with {}, {} \
+                     and {:016x}",
+                    first.scenario,
+                    first.params,
+                    first.status.as_ref(),
+                    first.steps,
+                    first.collisions,
+                    first.near_misses,
+                    first.digest,
+                    second.status.as_ref(),
+                    second.steps,
+                    second.collisions,
+                    second.near_misses,
+                    second.digest
+                );
+            }
+        }
+    }
+    let results: Vec<RunResult> = runs.into_iter().map(|(first, _)| first).collect();
+
+    let n_success = results
+        .iter()
+        .filter(|r| matches!(r.status, RunStatus::Success))
+        .count();
+    log::info!("{}/{} runs succeeded", n_success, results.len());
+
+    if let Some(path) = args.output {
+        let names: Vec<&str> = params.iter().map(|(name, _)| name.as_str()).collect();
+        match write_csv(&path, &names, &results) {
+            Ok(()) => log::info!("results written to {}", path),
+            Err(e) => log::error!("could not write results to {}: {}", path, e),
+        }
+    }
+
+    if check {
+        if mismatches > 0 {
+            log::error!(
+                "{}/{} runs are not deterministic",
+                mismatches,
+                results.len()
+            );
+            std::process::exit(1);
+        }
+        log::info!("all runs gave the same results twice");
+    }
+}
+
+fn unwrap_or_exit<T>(x: Result<T, String>) -> T {
+    match x {
+        Ok(x) => x,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Parses `name=v1,v2,...` arguments
+fn parse_params(args: &[String]) -> Result<Vec<(String, Vec<f64>)>, String> {
+    args.iter()
+        .map(|arg| {
+            let mut it = arg.splitn(2, '=');
+            let name = it.next().filter(|x| !x.is_empty());
+            let values = it.next();
+            let (name, values) = match (name, values) {
+                (Some(name), Some(values)) => (name, values),
+                _ => {
+                    return Err(format!(
+                        "invalid parameter `{}`, expected name=v1,v2,...",
+                        arg
+                    ))
+                }
+            };
+
+            let values = values
+                .split(',')
+                .map(|v| {
+                    v.trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid value `{}` for parameter `{}`", v, name))
+                })
+                .collect::<Result<Vec<f64>, String>>()?;
+
+            Ok((name.to_string(), values))
+        })
+        .collect::<Result<Vec<_>, String>>()
+        .and_then(|params| {
+            for (i, (name, _)) in params.iter().enumerate() {
+                if params[..i].iter().any(|(other, _)| other == name) {
+                    return Err(format!("parameter `{}` is given more than once", name));
+                }
+            }
+            Ok(params)
+        })
+}
+
+/// Makes the parameters of a run available to its scenario as the `params` table
+fn set_params(l: &Lua, params: &[(String, f64)]) -> mlua::Result<()> {
+    let t = l.create_table()?;
+    for (k, v) in params {
+        t.set(k.as_str(), *v)?;
+    }
+    l.globals().set("params", t)
 }
 
-fn run(mut state: EgregoriaState, name: &Path) {
-    let l = match mods::load(name) {
+/// Cartesian product of all the parameter values
+fn param_grid(params: &[(String, Vec<f64>)]) -> Vec<Vec<(String, f64)>> {
+    let mut grid: Vec<Vec<(String, f64)>> = vec![vec![]];
+    for (name, values) in params {
+        grid = grid
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |&v| {
+                    let mut c = combination.clone();
+                    c.push((name.clone(), v));
+                    c
+                })
+            })
+            .collect();
+    }
+    grid
+}
+
+fn run(name: &Path, params: &[(String, f64)], config: &RunConfig) -> RunResult {
+    let start = Instant::now();
+    let mut state = EgregoriaState::init_with(config.dispatch.clone());
+
+    if let Some(export) = &config.stats {
+        state.world.write_resource::<TrafficStats>().interval = export.interval;
+    }
+
+    let mut result = RunResult {
+        scenario: name.to_path_buf(),
+        params: params.to_vec(),
+        status: RunStatus::Error,
+        steps: 0,
+        sim_time: 0.0,
+        wall_time: 0.0,
+        collisions: 0,
+        near_misses: 0,
+        digest: 0,
+    };
+
+    let mut params_set = Ok(());
+    let l = mods::load_with(name, |l| params_set = set_params(l, params));
+
+    let l = match l {
         Some(l) => l,
         None => {
-            return;
+            return result;
         }
     };
+    if let Err(e) = params_set {
+        log::error!("could not set the parameters of {:?}: {}", name, e);
+        return result;
+    }
 
-    egregoria::lua::add_egregoria_lua_stdlib(&l, &mut state.world);
-    mods::eval_f(&l, "Init");
+    egregoria::lua::add_egregoria_lua_stdlib(&l);
+    if egregoria::lua::load_scenario_map(&l, &mut state.world).is_none() {
+        return result;
+    }
+    egregoria::lua::scenario_runner::init_scenario(&l, &mut state.world);
+    let mut callbacks = LuaCallbacks::new(&mut state.world);
 
-    for i in 1..1000 {
+    if let Some(dir) = &config.od {
+        let od = ODMatrix::from_csv(
+            dir.join("zones.csv"),
+            dir.join("matrix.csv"),
+            &state.world.read_resource::<Map>(),
+        );
+        match od {
+            Ok(od) => *state.world.write_resource::<ODMatrix>() = od,
+            Err(e) => {
+                log::error!("could not load OD matrix: {}", e);
+                return result;
+            }
+        }
+    }
+
+    result.status = RunStatus::Failure;
+    for i in 1..=config.max_steps {
         step(&mut state);
+        callbacks.fire(&l, &mut state.world);
 
-        let v: Option<bool> = mods::call_f(&l, "Success");
+        let v: Option<bool> =
+            egregoria::lua::with_world(&l, &mut state.world, |l| mods::call_f(l, "Success"))
+                .flatten();
         let v = match v {
             Some(x) => x,
             None => {
-                return;
+                result.status = RunStatus::Error;
+                break;
             }
         };
 
+        result.steps = i;
         if v {
-            log::info!("success for {:?} at iteration {}", name, i);
-            return;
+            result.status = RunStatus::Success;
+            break;
+        }
+    }
+
+    result.sim_time = state.world.read_resource::<TimeInfo>().time;
+    result.wall_time = start.elapsed().as_secs_f32();
+    {
+        let safety = state.world.read_resource::<SafetyLog>();
+        result.collisions = safety.count_since(IncidentKind::Collision, safety.scenario_start);
+        result.near_misses = safety.count_since(IncidentKind::NearMiss, safety.scenario_start);
+    }
+    result.digest = state_digest(&state.world);
+
+    if let Some(export) = &config.stats {
+        let dir = export.run_dir(name, params);
+        let mut stats = state.world.write_resource::<TrafficStats>();
+        stats.finish_interval(result.sim_time);
+        let r = stats
+            .export_csv(&dir)
+            .and_then(|_| state.world.read_resource::<SafetyLog>().export_csv(&dir));
+        if let Err(e) = r {
+            log::error!("could not export traffic stats to {:?}: {}", dir, e);
+        }
+    }
+
+    match result.status {
+        RunStatus::Success => log::info!(
+            "success for {:?} {:?} at iteration {}",
+            name,
+            params,
+            result.steps
+        ),
+        RunStatus::Failure => log::warn!("failure for {:?} {:?}", name, params),
+        RunStatus::Error => log::error!("error while running {:?} {:?}", name, params),
+    }
+
+    result
+}
+
+/// Hashes the position, direction and velocity of every entity, and the next number of the
+/// world's random generator. Two runs with the same digest almost surely went the same way.
+fn state_digest(world: &World) -> u64 {
+    let mut h = DefaultHasher::new();
+    let entities = world.entities();
+    let transforms = world.read_storage::<Transform>();
+    let kinematics = world.read_storage::<Kinematics>();
+    for (e, trans, kin) in (&entities, &transforms, kinematics.maybe()).join() {
+        e.id().hash(&mut h);
+        for v in &[
+            trans.position(),
+            trans.direction(),
+            kin.map_or(Vec2::ZERO, |k| k.velocity),
+        ] {
+            v.x.to_bits().hash(&mut h);
+            v.y.to_bits().hash(&mut h);
         }
     }
+    world
+        .write_resource::<RandProvider>()
+        .random::<u64>()
+        .hash(&mut h);
+    h.finish()
+}
 
-    log::warn!("failure for {:?}", name);
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn write_csv(path: &str, param_names: &[&str], results: &[RunResult]) -> std::io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+
+    write!(w, "scenario")?;
+    for name in param_names {
+        write!(w, ",{}", csv_field(name))?;
+    }
+    writeln!(
+        w,
+        ",status,steps,sim_time,wall_time_ms,step_time_ms,collisions,near_misses"
+    )?;
+
+    for r in results {
+        write!(w, "{}", csv_field(&r.scenario.to_string_lossy()))?;
+        for (_, v) in &r.params {
+            write!(w, ",{}", v)?;
+        }
+        let step_time = if r.steps > 0 {
+            r.wall_time * 1000.0 / r.steps as f32
+        } else {
+            0.0
+        };
+        writeln!(
+            w,
+            ",{},{},{:.3},{:.3},{:.4},{},{}",
+            r.status.as_ref(),
+            r.steps,
+            r.sim_time,
+            r.wall_time * 1000.0,
+            step_time,
+            r.collisions,
+            r.near_misses
+        )?;
+    }
+    w.flush()
 }
 
 const TIME_STEP: f64 = 1.0 / 30.0;
@@ -78,3 +540,50 @@ fn step(state: &mut EgregoriaState) {
     }
     state.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{param_grid, parse_params};
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_params() {
+        let params = parse_params(&args(&["cars=2,4, 8", "spacing=5"])).unwrap();
+        assert_eq!(
+            params,
+            vec![
+                ("cars".to_string(), vec![2.0, 4.0, 8.0]),
+                ("spacing".to_string(), vec![5.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_params() {
+        for bad in &["cars=", "=1", "cars", "cars=1,two", "cars=1,,2"] {
+            assert!(parse_params(&args(&[bad])).is_err(), "{} was accepted", bad);
+        }
+        assert!(parse_params(&args(&["cars=1", "cars=2"])).is_err());
+    }
+
+    #[test]
+    fn grid_is_cartesian_product() {
+        let params = parse_params(&args(&["a=1,2", "b=3,4,5"])).unwrap();
+        let grid = param_grid(&params);
+        assert_eq!(grid.len(), 6);
+        for a in &[1.0, 2.0] {
+            for b in &[3.0, 4.0, 5.0] {
+                let c = vec![("a".to_string(), *a), ("b".to_string(), *b)];
+                assert!(grid.contains(&c), "{:?} is missing", c);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_without_params_has_one_run() {
+        assert_eq!(param_grid(&[]), vec![vec![]]);
+    }
+}
@@ -10,7 +10,7 @@ function cartest.add_car(pos, dir, obj)
 end
 
 function Success()
-    local ok = true
+    local ok = world:collisions() == 0
     for i, car in ipairs(cartest.cars) do
         local arrived = world:pos(car.e):distance(car.obj) < 1.5
         ok = ok and arrived
@@ -0,0 +1,74 @@
+--- Building generator, see map_model/src/house_script.rs for ctx and the returned table.
+--- In local coordinates the road is towards -x, and the footprint is centered on the origin.
+
+function gen_sq(front, depth)
+    local w = rand_in(0.5, 0.8) * depth
+    local h = rand_in(0.4, 0.7) * front
+
+    local p = poly_rect(w, h)
+    p:translate(-p:barycenter())
+    return p
+end
+
+function gen_exterior_corner(front, depth)
+    local w = rand_in(0.4, 0.6) * depth
+    local h = rand_in(0.4, 0.6) * front
+
+    local p = poly_rect(w, h)
+
+    local corn_coeff = rand_in(0.5, 0.75)
+
+    local seg = math.floor(rand_in(0.0, 3.99))
+    p:split_segment(seg, corn_coeff)
+    p:extrude(seg, rand_in(0.15, 0.3) * math.min(w, h))
+
+    p:translate(-p:barycenter());
+    return p
+end
+
+local function count_kind(neighbours, kind)
+    local n = 0
+    for _, b in ipairs(neighbours) do
+        if b.kind == kind then
+            n = n + 1
+        end
+    end
+    return n
+end
+
+local function tallest(neighbours)
+    local floors = 1
+    for _, b in ipairs(neighbours) do
+        floors = math.max(floors, b.floors)
+    end
+    return floors
+end
+
+function gen_house(ctx)
+    local kind = ctx.kind
+    -- Corner shops next to commercial streets
+    if kind == "residential" and count_kind(ctx.neighbours, "commercial") >= 2 and rand_in(0.0, 1.0) < 0.3 then
+        kind = "commercial"
+    end
+
+    local floors
+    local footprint
+    if kind == "commercial" then
+        -- Keep in line with the surrounding buildings
+        floors = math.floor(rand_in(2.0, 4.99))
+        floors = math.max(floors, tallest(ctx.neighbours) - 1)
+        footprint = gen_sq(ctx.frontage, ctx.depth)
+    elseif kind == "industrial" then
+        floors = 1
+        footprint = gen_sq(ctx.frontage, ctx.depth)
+    else
+        floors = math.floor(rand_in(1.0, 2.99))
+        footprint = gen_exterior_corner(ctx.frontage, ctx.depth)
+    end
+
+    return {
+        footprint = footprint,
+        floors = floors,
+        kind = kind,
+    }
+end
@@ -0,0 +1,101 @@
+--- Actuated signals: the direction with the most waiting vehicles gets the green light,
+--- keeping it at least MIN_GREEN seconds and at most MAX_GREEN seconds when others wait.
+--- See map_model/src/light_script.rs for the format of the groups and of the controls.
+
+local MIN_GREEN = 8
+local MAX_GREEN = 30
+local ORANGE = 4
+
+--- Current phase of each intersection
+local states = {}
+
+--- Groups arriving from opposite directions share a phase
+local function phases(groups)
+    local ph = {}
+    local n = 0
+    for i, g in ipairs(groups) do
+        for j = 1, i - 1 do
+            local o = groups[j].direction
+            if g.direction:x() * o:x() + g.direction:y() * o:y() < -0.7 then
+                ph[i] = ph[j]
+                break
+            end
+        end
+        if ph[i] == nil then
+            n = n + 1
+            ph[i] = n
+        end
+    end
+    return ph, n
+end
+
+local function controls(ph, phase, state)
+    local c = {}
+    for i, p in ipairs(ph) do
+        if p == phase then
+            c[i] = state
+        else
+            c[i] = "red"
+        end
+    end
+    return c
+end
+
+function schedule(groups, inter)
+    states[inter] = nil
+    local ph, n = phases(groups)
+    if n < 2 then
+        return controls(ph, 1, "always")
+    end
+    return controls(ph, 1, "green")
+end
+
+function tick(time, groups, inter)
+    local ph, n = phases(groups)
+    if n < 2 then
+        return nil
+    end
+
+    local s = states[inter]
+    if s == nil then
+        s = { phase = 1, since = time }
+        states[inter] = s
+    end
+    local elapsed = time - s.since
+
+    if s.next ~= nil then
+        if elapsed < ORANGE then
+            return nil
+        end
+        s.phase = s.next
+        s.next = nil
+        s.since = time
+        return controls(ph, s.phase, "green")
+    end
+
+    if elapsed < MIN_GREEN then
+        return nil
+    end
+
+    local waiting = {}
+    for p = 1, n do
+        waiting[p] = 0
+    end
+    for i, g in ipairs(groups) do
+        waiting[ph[i]] = waiting[ph[i]] + g.waiting
+    end
+
+    local best = nil
+    for p = 1, n do
+        if p ~= s.phase and (best == nil or waiting[p] > waiting[best]) then
+            best = p
+        end
+    end
+
+    if waiting[best] > waiting[s.phase] or (elapsed >= MAX_GREEN and waiting[best] > 0) then
+        s.next = best
+        s.since = time
+        return controls(ph, s.phase, "orange")
+    end
+    return nil
+end
@@ -0,0 +1,11 @@
+local cartest = require "cartest"
+
+-- Sweepable from the cli, e.g. `goria lua/scenarios/car_line.lua -p cars=2,4,8 -p spacing=6,10`
+function Init()
+    local n = params.cars or 3
+    local spacing = params.spacing or 6.0
+    for i = 1, n do
+        local x = -spacing * i
+        cartest.add_car(vec2(x, 0.0), right, vec2(x + 30.0, 0.0))
+    end
+end
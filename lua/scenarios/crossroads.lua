@@ -0,0 +1,25 @@
+local cartest = require "cartest"
+
+local policies = { "smart", "lights", "stop_signs", "reservations" }
+
+local center
+
+--- Builds its own crossroads instead of using the loaded map.
+--- Sweepable on the control of the center, e.g. `goria lua/scenarios/crossroads.lua -p policy=1,4`
+--- with policy 1 = smart, 2 = lights, 3 = stop signs, 4 = reservations
+Map = {
+    build = function()
+        center = world:add_intersection(vec2(0.0, 0.0))
+        local pattern = { lanes = 1, parking = false }
+        for _, dir in ipairs({ up, down, left, right }) do
+            world:connect(world:add_intersection(dir * 80.0), center, pattern)
+        end
+    end,
+}
+
+function Init()
+    world:set_light_policy(center, policies[params.policy or 1])
+
+    cartest.add_car(vec2(-40.0, -2.0), right, vec2(40.0, -2.0))
+    cartest.add_car(vec2(2.0, -40.0), up, vec2(2.0, 40.0))
+end
@@ -4,12 +4,29 @@
 --- DateTime: 11/08/2020 11:05
 ---
 
+--- Scripts run sandboxed: `io`, `os`, `debug`, `dofile` and `loadfile` are unavailable,
+--- `require` only loads Lua source files from `lua/` and from the mod package of the script,
+--- and each call from the game has an instruction budget.
+
+--- Only valid while the game is calling the script, don't keep references to it
 ---@class World
 world = world
 
 ---@class Draw
 draw = draw
 
+--- Parameters given by the headless cli (`-p name=v1,v2`), empty otherwise
+---@type table<string, number>
+params = params
+
+--- Map of a scenario, loaded before Init in place of the current one. All fields are optional:
+--- file       path of a map saved by the game, such as "world/map.bc"
+--- generator  "testfield", "grid" (with pos and size), "doublecircle" (with pos) or "paris"
+--- build      function making the layout with the world methods, called after the rest
+--- The vehicles and pedestrians are removed when the map is replaced.
+---@type table
+Map = Map
+
 ---@class Vec2
 ---@class Entity
 ---@class Color
@@ -45,4 +62,121 @@ function world.pos(world, e) end
 ---@param dir Vec2
 ---@param objective Vec2
 ---@return Entity
-function world.add_car(world, pos, dir, objective) end
\ No newline at end of file
+function world.add_car(world, pos, dir, objective) end
+---@class Intersection
+---@class Road
+
+--- Removes every road, intersection and house
+---@param world World
+function world.clear(world) end
+
+---@param world World
+---@param pos Vec2
+---@return Intersection
+function world.add_intersection(world, pos) end
+
+--- Builds a straight road, pattern fields are optional:
+--- { lanes = 1, sidewalks = true, parking = true, one_way = false }
+---@param world World
+---@param src Intersection
+---@param dst Intersection
+---@param pattern table
+---@return Road
+function world.connect(world, src, dst, pattern) end
+
+---@param world World
+---@param road Road
+---@param pos Vec2
+---@return Intersection
+function world.split_road(world, road, pos) end
+
+--- policy is one of "no_lights", "stop_signs", "lights", "smart", "reservations" or "custom",
+--- script being the path of the Lua policy for "custom", such as lua/lights/actuated.lua
+---@param world World
+---@param inter Intersection
+---@param policy string
+---@param script string
+function world.set_light_policy(world, inter, policy, script) end
+
+--- Only the given fields change: { left_turns = true, back_turns = false }
+---@param world World
+---@param inter Intersection
+---@param policy table
+function world.set_turn_policy(world, inter, policy) end
+
+---@class Lane
+
+---@param world World
+---@param e Entity
+---@return number
+function world.speed(world, e) end
+
+--- "parked", "unparking", "driving" or "parking", nil if e is not a vehicle
+---@param world World
+---@param e Entity
+---@return string
+function world.vehicle_state(world, e) end
+
+---@param world World
+---@param e Entity
+---@return number
+function world.wait_time(world, e) end
+
+--- { kind, remaining_points, remaining_steps, terminal, ended, objective, destination }
+--- kind is one of "none", "wait", "simple" or "route"
+---@param world World
+---@param e Entity
+---@return table
+function world.itinerary(world, e) end
+
+---@param world World
+---@param pos Vec2
+---@param radius number
+---@return Entity[]
+function world.vehicles_around(world, pos, radius) end
+
+---@param world World
+---@param pos Vec2
+---@param radius number
+---@return Entity[]
+function world.pedestrians_around(world, pos, radius) end
+
+--- Simulated time, in seconds
+---@param world World
+---@return number
+function world.time(world) end
+
+---@param world World
+---@param speed number
+function world.set_time_speed(world, speed) end
+
+--- { kind, intersection, road, lane, lane_kind, building_kind, floors, entrance }
+--- kind is one of "intersection", "lane", "house" or "ground",
+--- building_kind is one of "residential", "commercial" or "industrial"
+---@param world World
+---@param pos Vec2
+---@return table
+function world.map_at(world, pos) end
+
+--- Collisions between agents since the scenario was initialized, or since `since` (see
+--- world:time()), for scenarios asserting there are none
+---@param world World
+---@param since number|nil
+---@return number
+function world.collisions(world, since) end
+
+--- Pairs of agents that came close to colliding since the scenario was initialized, or since
+--- `since`
+---@param world World
+---@param since number|nil
+---@return number
+function world.near_misses(world, since) end
+
+--- Callbacks, define the ones the script needs as globals.
+--- OnTick(dt)                          every simulation step, dt in seconds
+--- OnVehicleArrived(e)                 reached the end of its itinerary
+--- OnVehicleParked(e)
+--- OnVehicleUnparked(e)
+--- OnPedestrianArrived(e)              reached the end of its itinerary
+--- OnEntityDeleted(e)
+--- OnLightChanged(inter, lane, state)  state is "red", "orange" or "green"
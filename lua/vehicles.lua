@@ -0,0 +1,98 @@
+--- Vehicle types, loaded when the game starts.
+--- Each call to vehicle_type declares a type; missing fields are taken from the default car.
+---
+--- name                mandatory, used to refer to the type
+--- width, height       length along the direction of travel and lateral size, in meters
+--- mass                in kg
+--- acceleration        in m/s²
+--- deceleration        in m/s²
+--- min_turning_radius  in meters, for the direct steering
+--- cruising_speed      in m/s
+--- ang_acc             angular acceleration, in rad/s², for the direct steering
+--- car_following       "rules", "idm" or a table of IDM parameters
+---                     (time_headway, min_gap, max_acceleration, comfortable_deceleration, delta)
+--- steering            "direct" to rotate towards the objective (the default), "bicycle" for the
+---                     kinematic bicycle model following the itinerary by pure pursuit, or a table
+---                     of its parameters (wheelbase in m, max_steering_angle in rad,
+---                     steering_rate in rad/s, lookahead_time in s, min_lookahead in m),
+---                     wheelbase defaults to 0.6 * width
+--- sprite              path of the image, scale is its size in meters
+--- colors              list of { hex, relative frequency }
+--- spawn_weight        relative frequency when spawning random vehicles, 0 to never spawn
+--- emergency           goes through red lights and stop signs with caution, others yield to it
+
+vehicle_type {
+    name = "car",
+    width = 4.5,
+    height = 2.0,
+    mass = 1000.0,
+    acceleration = 3.0,
+    deceleration = 9.0,
+    min_turning_radius = 3.0,
+    cruising_speed = 15.0,
+    ang_acc = 1.0,
+    car_following = "rules",
+    sprite = "resources/car.png",
+    scale = 4.5,
+    colors = {
+        { 0x222222, 0.22 }, -- Black
+        { 0xffffff, 0.19 }, -- White
+        { 0x666666, 0.17 }, -- Gray
+        { 0xb8b8b8, 0.14 }, -- Silver
+        { 0x1a3c70, 0.1 }, -- Blue
+        { 0xd82200, 0.1 }, -- Red
+        { 0x7c4b24, 0.02 }, -- Brown
+        { 0xd4c678, 0.015 }, -- Gold
+        { 0x72cb19, 0.015 }, -- Green
+    },
+    spawn_weight = 1.0,
+}
+
+vehicle_type {
+    name = "bus",
+    width = 9.0,
+    mass = 10000.0,
+    acceleration = 2.0,
+    min_turning_radius = 5.0,
+    cruising_speed = 10.0,
+    ang_acc = 0.8,
+    car_following = {
+        time_headway = 1.7,
+        min_gap = 2.5,
+        max_acceleration = 0.8,
+        comfortable_deceleration = 1.2,
+    },
+    scale = 9.0,
+    colors = { { 0xd82200, 1.0 } },
+    spawn_weight = 0.0,
+}
+
+vehicle_type {
+    name = "van",
+    width = 5.5,
+    mass = 2500.0,
+    acceleration = 2.5,
+    deceleration = 8.0,
+    min_turning_radius = 4.0,
+    cruising_speed = 13.0,
+    scale = 5.5,
+    colors = {
+        { 0xffffff, 0.7 },
+        { 0xb8b8b8, 0.2 },
+        { 0x1a3c70, 0.1 },
+    },
+    spawn_weight = 0.08,
+}
+
+vehicle_type {
+    name = "ambulance",
+    width = 6.0,
+    mass = 3500.0,
+    acceleration = 3.5,
+    min_turning_radius = 4.0,
+    cruising_speed = 20.0,
+    scale = 6.0,
+    colors = { { 0xffffff, 1.0 } },
+    emergency = true,
+    spawn_weight = 0.0,
+}
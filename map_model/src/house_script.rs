@@ -0,0 +1,86 @@
+//! Building generation in Lua. The script defines `gen_house(ctx)` where `ctx` is
+//! `{ frontage = 30, depth = 25, kind = "residential", neighbours = { { kind, floors, distance }, ... } }`
+//! and returns a footprint polygon, or `{ footprint = poly, floors = 2, kind = "commercial",
+//! entrance = vec2(...) }` where every field but the footprint is optional.
+//! Scripts that don't define `gen_house` are evaluated and must return a footprint.
+
+use crate::{BuildingKind, HouseContext, HouseShape};
+use mods::mlua::{Error, Lua, Result, Table, Value};
+use mods::{LuaPolygon, LuaVec2};
+
+fn context_table<'lua>(l: &'lua Lua, ctx: &HouseContext) -> Result<Table<'lua>> {
+    let t = l.create_table()?;
+    t.set("frontage", ctx.frontage)?;
+    t.set("depth", ctx.depth)?;
+    t.set("kind", ctx.kind.name())?;
+
+    let neighbours = l.create_table()?;
+    for (i, n) in ctx.neighbours.iter().enumerate() {
+        let nt = l.create_table()?;
+        nt.set("kind", n.kind.name())?;
+        nt.set("floors", n.floors)?;
+        nt.set("distance", n.distance)?;
+        neighbours.set(i + 1, nt)?;
+    }
+    t.set("neighbours", neighbours)?;
+    Ok(t)
+}
+
+fn parse_shape(v: Value, ctx: &HouseContext) -> Result<HouseShape> {
+    match v {
+        Value::UserData(ud) => Ok(HouseShape {
+            footprint: ud.borrow::<LuaPolygon>()?.0.clone(),
+            floors: 1,
+            kind: ctx.kind,
+            entrance: None,
+        }),
+        Value::Table(t) => {
+            let footprint: LuaPolygon = t.get("footprint")?;
+            let kind = match t.get::<_, Option<String>>("kind")? {
+                Some(name) => BuildingKind::from_name(&name).ok_or_else(|| {
+                    Error::RuntimeError(format!("unknown building kind {}", name))
+                })?,
+                None => ctx.kind,
+            };
+            Ok(HouseShape {
+                footprint: footprint.0,
+                floors: t.get::<_, Option<u32>>("floors")?.unwrap_or(1).max(1),
+                kind,
+                entrance: t.get::<_, Option<LuaVec2>>("entrance")?.map(|e| e.0),
+            })
+        }
+        _ => Err(Error::RuntimeError(format!(
+            "gen_house should return a polygon or a table, got a {}",
+            v.type_name()
+        ))),
+    }
+}
+
+fn call_gen_house(l: &Lua, ctx: &HouseContext) -> Option<HouseShape> {
+    let t = context_table(l, ctx).map_err(|e| error!("{}", e)).ok()?;
+    let v: Value = mods::call_with(l, "gen_house", t)?;
+    parse_shape(v, ctx).map_err(|e| error!("{}", e)).ok()
+}
+
+/// `seed` is given to the script's `rand_in`, so the same lot always gets the same building
+pub(crate) fn generate(ctx: &HouseContext, seed: u64) -> Option<HouseShape> {
+    let script = mods::house_script();
+    let generated = mods::with_script(&script, |l| {
+        if !mods::is_defined(l, "gen_house") {
+            return None;
+        }
+        mods::seed_rand(l, seed);
+        Some(call_gen_house(l, ctx))
+    });
+    if let Some(shape) = generated {
+        return shape;
+    }
+
+    // Scripts returning the footprint directly
+    mods::eval_script::<LuaPolygon, _>(&script).map(|p| HouseShape {
+        footprint: p.0,
+        floors: 1,
+        kind: ctx.kind,
+        entrance: None,
+    })
+}
@@ -1,5 +1,6 @@
-use crate::{Map, ProjectKind};
+use crate::{house_script, Map, ProjectKind};
 use geom::polygon::Polygon;
+use geom::rect::Rect;
 use geom::Vec2;
 use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
@@ -8,18 +9,121 @@ new_key_type! {
     pub struct HouseID;
 }
 
+/// Buildings closer than this to a lot are given to the generator as neighbours, in meters
+const NEIGHBOURS_RADIUS: f32 = 50.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BuildingKind {
+    Residential,
+    Commercial,
+    Industrial,
+}
+
+impl BuildingKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildingKind::Residential => "residential",
+            BuildingKind::Commercial => "commercial",
+            BuildingKind::Industrial => "industrial",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "residential" => BuildingKind::Residential,
+            "commercial" => BuildingKind::Commercial,
+            "industrial" => BuildingKind::Industrial,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Neighbour {
+    pub kind: BuildingKind,
+    pub floors: u32,
+    /// From the lot's front, in meters
+    pub distance: f32,
+}
+
+/// What the generator knows about the lot a building is made for
+#[derive(Clone, Debug)]
+pub struct HouseContext {
+    /// Length of the lot along the road, in meters
+    pub frontage: f32,
+    /// Depth of the lot away from the road, in meters
+    pub depth: f32,
+    /// Zone of the lot, the generator may build something else
+    pub kind: BuildingKind,
+    pub neighbours: Vec<Neighbour>,
+}
+
+/// A building as returned by the generator, in its own coordinates: the road is towards -x
+/// and the footprint is around the origin
+#[derive(Clone)]
+pub struct HouseShape {
+    pub footprint: Polygon,
+    pub floors: u32,
+    pub kind: BuildingKind,
+    pub entrance: Option<Vec2>,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct House {
     pub id: HouseID,
     pub exterior: Polygon,
+    pub kind: BuildingKind,
+    pub floors: u32,
+    /// Where agents enter the building, on the side of the road
+    pub entrance: Vec2,
 }
 
 impl House {
-    pub fn try_make(map: &mut Map, at: Vec2, axis: Vec2) -> Option<HouseID> {
-        let mut exterior = mods::gen_house()?;
+    /// Makes a building on the lot in front of `at`, `axis` pointing away from the road
+    pub fn try_make(
+        map: &mut Map,
+        at: Vec2,
+        axis: Vec2,
+        frontage: f32,
+        depth: f32,
+        kind: BuildingKind,
+    ) -> Option<HouseID> {
+        let neighbours = map
+            .spatial_map
+            .query_rect(Rect::new(
+                at.x - NEIGHBOURS_RADIUS,
+                at.y - NEIGHBOURS_RADIUS,
+                NEIGHBOURS_RADIUS * 2.0,
+                NEIGHBOURS_RADIUS * 2.0,
+            ))
+            .filter_map(|obj| match obj {
+                ProjectKind::House(h) => map.houses.get(h),
+                _ => None,
+            })
+            .map(|h| Neighbour {
+                kind: h.kind,
+                floors: h.floors,
+                distance: h.entrance.distance(at),
+            })
+            .filter(|n| n.distance < NEIGHBOURS_RADIUS)
+            .collect();
 
+        let ctx = HouseContext {
+            frontage,
+            depth,
+            kind,
+            neighbours,
+        };
+        let HouseShape {
+            footprint: mut exterior,
+            floors,
+            kind,
+            entrance,
+        } = house_script::generate(&ctx, (at.x.to_bits() as u64) << 32 | at.y.to_bits() as u64)?;
+
+        let offset = at + axis * exterior.bcircle().radius;
         exterior.rotate(axis);
-        exterior.translate(at + axis * exterior.bcircle().radius);
+        exterior.translate(offset);
 
         let bcirc = exterior.bcircle();
 
@@ -42,7 +146,18 @@ impl House {
             }
         }
 
-        let id = map.houses.insert_with_key(move |id| Self { id, exterior });
+        let entrance = match entrance {
+            Some(e) => offset + e.rotated_by(axis),
+            None => exterior.project(at),
+        };
+
+        let id = map.houses.insert_with_key(move |id| Self {
+            id,
+            exterior,
+            kind,
+            floors,
+            entrance,
+        });
         map.spatial_map.insert_house(&map.houses[id]);
         Some(id)
     }
@@ -1,6 +1,7 @@
+use crate::light_script::LightScripts;
 use crate::{
-    Intersections, LaneID, Lanes, LightPolicy, RoadID, Roads, TraverseDirection, Turn, TurnID,
-    TurnPolicy,
+    Intersections, LaneID, Lanes, LightPolicy, RoadID, Roads, TrafficControl, TraverseDirection,
+    Turn, TurnID, TurnKind, TurnPolicy,
 };
 use geom::polygon::Polygon;
 use geom::pseudo_angle;
@@ -9,6 +10,7 @@ use geom::Vec2;
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use slotmap::new_key_type;
+use std::collections::HashMap;
 
 new_key_type! {
     pub struct IntersectionID;
@@ -20,6 +22,35 @@ impl IntersectionID {
     }
 }
 
+/// How the paths of two turns of the same intersection interact
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The paths cross each other
+    Crossing,
+    /// Both end on the same lane
+    Merging,
+    /// Both start from the same lane
+    Diverging,
+}
+
+impl ConflictKind {
+    fn between(a: &Turn, b: &Turn) -> Option<Self> {
+        // Pedestrians don't get in each other's way
+        if a.kind != TurnKind::Driving && b.kind != TurnKind::Driving {
+            return None;
+        }
+        if a.id.dst == b.id.dst {
+            return Some(ConflictKind::Merging);
+        }
+        if a.id.src == b.id.src {
+            return Some(ConflictKind::Diverging);
+        }
+        a.points
+            .intersection(&b.points)
+            .map(|_| ConflictKind::Crossing)
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Intersection {
     pub id: IntersectionID,
@@ -27,6 +58,10 @@ pub struct Intersection {
 
     turns: Vec<Turn>,
 
+    /// Recomputed with the turns
+    #[serde(skip)]
+    conflicts: HashMap<TurnID, Vec<(TurnID, ConflictKind)>>,
+
     // sorted by angle
     pub roads: Vec<RoadID>,
 
@@ -42,6 +77,7 @@ impl Intersection {
             id,
             pos,
             turns: Default::default(),
+            conflicts: Default::default(),
             roads: Default::default(),
             turn_policy: Default::default(),
             light_policy: Default::default(),
@@ -76,10 +112,71 @@ impl Intersection {
         for turn in self.turns.iter_mut() {
             turn.make_points(lanes);
         }
+        self.update_turn_controls(lanes, roads);
+
+        self.update_conflicts();
+    }
+
+    pub(crate) fn update_turn_controls(&mut self, lanes: &Lanes, roads: &Roads) {
+        for turn in self.turns.iter_mut() {
+            // Crosswalks follow the lights of the road they cross
+            turn.control = if turn.kind.is_crosswalk() {
+                roads
+                    .get(lanes[turn.id.src].parent)
+                    .and_then(|r| {
+                        r.incoming_lanes_to(self.id)
+                            .iter()
+                            .find(|(_, kind)| kind.needs_light())
+                    })
+                    .map_or(TrafficControl::Always, |&(id, _)| {
+                        lanes[id].control.pedestrian()
+                    })
+            } else {
+                TrafficControl::Always
+            };
+        }
     }
 
-    pub fn update_traffic_control(&self, lanes: &mut Lanes, roads: &Roads) {
-        self.light_policy.apply(self, lanes, roads);
+    pub(crate) fn update_conflicts(&mut self) {
+        self.conflicts.clear();
+        for (i, a) in self.turns.iter().enumerate() {
+            for b in &self.turns[i + 1..] {
+                if let Some(kind) = ConflictKind::between(a, b) {
+                    self.conflicts.entry(a.id).or_default().push((b.id, kind));
+                    self.conflicts.entry(b.id).or_default().push((a.id, kind));
+                }
+            }
+        }
+    }
+
+    /// Turns of this intersection whose paths interact with the given turn
+    pub fn conflicts(&self, turn: TurnID) -> impl Iterator<Item = (TurnID, ConflictKind)> + '_ {
+        self.conflicts
+            .get(&turn)
+            .map(|x| x.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .copied()
+    }
+
+    /// Incoming lanes needing a light, grouped by road
+    pub fn incoming_lane_groups(&self, roads: &Roads) -> Vec<Vec<LaneID>> {
+        self.roads
+            .iter()
+            .map(|&x| {
+                roads[x]
+                    .incoming_lanes_to(self.id)
+                    .iter()
+                    .filter(|(_, kind)| kind.needs_light())
+                    .map(|&(id, _)| id)
+                    .collect::<Vec<_>>()
+            })
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
+
+    pub fn update_traffic_control(&self, lanes: &mut Lanes, roads: &Roads, scripts: &LightScripts) {
+        self.light_policy.apply(self, lanes, roads, scripts);
     }
 
     pub fn update_interface_radius(&self, roads: &mut Roads) {
@@ -180,3 +277,95 @@ impl Intersection {
         &self.turns
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ConflictKind;
+    use crate::{Intersection, LanePatternBuilder, Map, RoadID, Turn, TurnKind};
+    use geom::vec2;
+
+    /// Intersection at the origin with two-way roads of one lane each way to the west, east,
+    /// south and north
+    fn four_way() -> (Map, Vec<RoadID>) {
+        let mut map = Map::empty();
+        let center = map.add_intersection(vec2(0.0, 0.0));
+        let pattern = LanePatternBuilder::new().parking(false).build();
+        let roads = [
+            vec2(-100.0, 0.0),
+            vec2(100.0, 0.0),
+            vec2(0.0, -100.0),
+            vec2(0.0, 100.0),
+        ]
+        .iter()
+        .map(|&pos| {
+            let arm = map.add_intersection(pos);
+            map.connect_straight(arm, center, &pattern)
+        })
+        .collect();
+        (map, roads)
+    }
+
+    fn center(map: &Map) -> &Intersection {
+        map.intersections()
+            .values()
+            .find(|i| i.pos == vec2(0.0, 0.0))
+            .unwrap()
+    }
+
+    /// The driving turn from the road `from` to the road `to`
+    fn turn<'a>(map: &'a Map, from: RoadID, to: RoadID) -> &'a Turn {
+        center(map)
+            .turns()
+            .iter()
+            .find(|t| {
+                t.kind == TurnKind::Driving
+                    && map.lanes()[t.id.src].parent == from
+                    && map.lanes()[t.id.dst].parent == to
+            })
+            .unwrap()
+    }
+
+    fn conflict(map: &Map, a: &Turn, b: &Turn) -> Option<ConflictKind> {
+        center(map)
+            .conflicts(a.id)
+            .find(|&(id, _)| id == b.id)
+            .map(|x| x.1)
+    }
+
+    #[test]
+    fn four_way_conflicts() {
+        let (map, roads) = four_way();
+        let (w, e, s, n) = (roads[0], roads[1], roads[2], roads[3]);
+
+        let w_n = turn(&map, w, n);
+        let e_s = turn(&map, e, s);
+        let e_w = turn(&map, e, w);
+        let s_n = turn(&map, s, n);
+        let w_e = turn(&map, w, e);
+
+        // Left turns cross the opposing straight movement and the crossing straight movements
+        // cross each other
+        assert_eq!(conflict(&map, w_n, e_w), Some(ConflictKind::Crossing));
+        assert_eq!(conflict(&map, w_e, s_n), Some(ConflictKind::Crossing));
+        // Opposing left turns pass in front of each other
+        assert_eq!(conflict(&map, w_n, e_s), None);
+        // Into the same lane
+        assert_eq!(conflict(&map, w_n, s_n), Some(ConflictKind::Merging));
+        // Out of the same lane
+        assert_eq!(conflict(&map, w_n, w_e), Some(ConflictKind::Diverging));
+        // Conflicts go both ways
+        assert_eq!(conflict(&map, e_w, w_n), Some(ConflictKind::Crossing));
+
+        let crosswalks: Vec<&Turn> = center(&map)
+            .turns()
+            .iter()
+            .filter(|t| t.kind.is_crosswalk())
+            .collect();
+        assert!(crosswalks.len() >= 2);
+        for a in &crosswalks {
+            for b in &crosswalks {
+                assert_eq!(conflict(&map, a, b), None);
+            }
+        }
+    }
+}
@@ -9,6 +9,12 @@ new_key_type! {
     pub struct LaneID;
 }
 
+impl LaneID {
+    pub fn as_ffi(self) -> u64 {
+        self.0.as_ffi()
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum LaneKind {
     Driving,
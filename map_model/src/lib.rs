@@ -10,10 +10,12 @@ macro_rules! unwrap_or {
 #[macro_use]
 extern crate log;
 
+mod house_script;
 mod housing;
 mod intersection;
 mod lane;
 mod light_policy;
+pub mod light_script;
 mod map;
 mod mapgen;
 mod parking;
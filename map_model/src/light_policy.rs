@@ -1,4 +1,7 @@
-use crate::{Intersection, LaneID, Lanes, Roads, TrafficControl, TrafficLightSchedule};
+use crate::light_script::{LightScripts, DEFAULT_LIGHT_SCRIPT};
+use crate::{
+    light_script, Intersection, LaneID, Lanes, Roads, TrafficControl, TrafficLightSchedule,
+};
 use imgui_inspect::{
     imgui::{im_str, Ui},
     InspectArgsDefault, InspectRenderDefault,
@@ -6,12 +9,16 @@ use imgui_inspect::{
 use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum LightPolicy {
     NoLights,
     StopSigns,
     Lights,
     Smart,
+    /// Vehicles reserve their path through the intersection
+    Reservations,
+    /// Controls given by a Lua script, see the `light_script` module
+    Custom(String),
 }
 
 impl Default for LightPolicy {
@@ -21,20 +28,14 @@ impl Default for LightPolicy {
 }
 
 impl LightPolicy {
-    pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
-        let in_road_lanes: Vec<Vec<LaneID>> = inter
-            .roads
-            .iter()
-            .map(|&x| {
-                roads[x]
-                    .incoming_lanes_to(inter.id)
-                    .iter()
-                    .filter(|(_, kind)| kind.needs_light())
-                    .map(|&(id, _)| id)
-                    .collect::<Vec<_>>()
-            })
-            .filter(|v| !v.is_empty())
-            .collect();
+    pub fn apply(
+        &self,
+        inter: &Intersection,
+        lanes: &mut Lanes,
+        roads: &Roads,
+        scripts: &LightScripts,
+    ) {
+        let in_road_lanes = inter.incoming_lane_groups(roads);
 
         for incoming_lanes in &in_road_lanes {
             for &lane in incoming_lanes {
@@ -61,10 +62,24 @@ impl LightPolicy {
                     self.stop_signs(in_road_lanes, lanes);
                 }
             }
+            LightPolicy::Reservations => {
+                for lane in in_road_lanes.into_iter().flatten() {
+                    lanes[lane].control = TrafficControl::Reservation;
+                }
+            }
+            LightPolicy::Custom(script) => {
+                let controls = unwrap_or!(
+                    light_script::schedule(scripts, script, inter, &in_road_lanes, lanes),
+                    return
+                );
+                for (lane, control) in controls {
+                    lanes[lane].control = control;
+                }
+            }
         }
     }
 
-    fn stop_signs(self, in_road_lanes: Vec<Vec<LaneID>>, lanes: &mut Lanes) {
+    fn stop_signs(&self, in_road_lanes: Vec<Vec<LaneID>>, lanes: &mut Lanes) {
         for incoming_lanes in in_road_lanes {
             for lane in incoming_lanes {
                 lanes[lane].control = TrafficControl::StopSign;
@@ -72,7 +87,7 @@ impl LightPolicy {
         }
     }
 
-    fn lights(self, in_road_lanes: Vec<Vec<LaneID>>, inter: &Intersection, lanes: &mut Lanes) {
+    fn lights(&self, in_road_lanes: Vec<Vec<LaneID>>, inter: &Intersection, lanes: &mut Lanes) {
         let n_cycles = (in_road_lanes.len() + 1) / 2;
         let cycle_size = 14;
         let orange_length = 4;
@@ -118,6 +133,8 @@ impl InspectRenderDefault<LightPolicy> for LightPolicy {
             LightPolicy::StopSigns => 1,
             LightPolicy::Lights => 2,
             LightPolicy::Smart => 3,
+            LightPolicy::Reservations => 4,
+            LightPolicy::Custom(_) => 5,
         };
 
         let changed = imgui_inspect::imgui::ComboBox::new(&im_str!("{}", label))
@@ -129,15 +146,23 @@ impl InspectRenderDefault<LightPolicy> for LightPolicy {
                     &im_str!("Stop signs"),
                     &im_str!("Lights"),
                     &im_str!("Smart"),
+                    &im_str!("Reservations"),
+                    &im_str!("Custom"),
                 ],
             );
 
+        if let LightPolicy::Custom(script) = &**p {
+            ui.text(im_str!("{}", script));
+        }
+
         if changed {
             match id {
                 0 => **p = LightPolicy::NoLights,
                 1 => **p = LightPolicy::StopSigns,
                 2 => **p = LightPolicy::Lights,
                 3 => **p = LightPolicy::Smart,
+                4 => **p = LightPolicy::Reservations,
+                5 => **p = LightPolicy::Custom(DEFAULT_LIGHT_SCRIPT.to_string()),
                 _ => unreachable!(),
             }
         }
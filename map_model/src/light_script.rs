@@ -0,0 +1,149 @@
+//! Traffic control policies written in Lua, see `LightPolicy::Custom`.
+//!
+//! The script defines `schedule(groups, inter)` and optionally `tick(time, groups, inter)`,
+//! both returning a list with one entry per group: a control, or a list of controls, one per
+//! lane of the group. `tick` is called every simulated second and may return nil to keep the
+//! current controls. `inter` is a number identifying the intersection, since one script
+//! can drive several of them.
+//!
+//! Each map has its own interpreter of each script, so scripts can keep state in their globals
+//! without it being shared between worlds.
+//!
+//! A control is one of `"always"`, `"stop"`, `"reservation"`, a fixed `"green"`, `"orange"`
+//! or `"red"` light, or a light cycle `{ green = 10, orange = 4, red = 14, offset = 0 }`
+//! in seconds.
+
+use crate::{Intersection, LaneID, Lanes, TrafficControl, TrafficLightSchedule};
+use geom::Vec2;
+use mods::mlua::{Lua, Table, Value};
+use mods::{LuaVec2, ScriptCache};
+
+/// Script used when `LightPolicy::Custom` is picked in the inspector
+pub const DEFAULT_LIGHT_SCRIPT: &str = "lua/lights/actuated.lua";
+
+/// Control to give to each incoming lane
+pub type LaneControls = Vec<(LaneID, TrafficControl)>;
+
+/// Interpreters of the light scripts used by a map, one per script. Modified scripts are only
+/// picked up after `refresh`, which the simulation calls once per tick.
+pub type LightScripts = ScriptCache;
+
+/// `{ lanes = n, direction = Vec2, waiting = n }` for each group, `waiting` being the number
+/// of vehicles stopped on the group's lanes, only known in `tick`
+fn groups_table<'lua>(
+    l: &'lua Lua,
+    inter: &Intersection,
+    groups: &[Vec<LaneID>],
+    lanes: &Lanes,
+    waiting: Option<&[u32]>,
+) -> mods::mlua::Result<Table<'lua>> {
+    let t = l.create_table()?;
+    for (i, group) in groups.iter().enumerate() {
+        let g = l.create_table()?;
+        g.set("lanes", group.len())?;
+        // Direction in which the vehicles arrive at the intersection
+        let dir = group
+            .first()
+            .map(|&id| -lanes[id].orientation_from(inter.id))
+            .unwrap_or(Vec2::UNIT_X);
+        g.set("direction", LuaVec2(dir))?;
+        if let Some(&w) = waiting.and_then(|w| w.get(i)) {
+            g.set("waiting", w)?;
+        }
+        t.set(i + 1, g)?;
+    }
+    Ok(t)
+}
+
+fn parse_control(v: &Value) -> Option<TrafficControl> {
+    let fixed = |green, orange, red| {
+        TrafficControl::Light(TrafficLightSchedule::from_basic(green, orange, red, 0))
+    };
+    match v {
+        Value::String(s) => Some(match s.to_str().ok()? {
+            "always" => TrafficControl::Always,
+            "stop" => TrafficControl::StopSign,
+            "reservation" => TrafficControl::Reservation,
+            "green" => fixed(1, 0, 0),
+            "orange" => fixed(0, 1, 0),
+            "red" => fixed(0, 0, 1),
+            x => {
+                error!("unknown traffic control {}", x);
+                return None;
+            }
+        }),
+        Value::Table(t) => {
+            let get = |key: &str| t.get::<_, Option<u32>>(key).ok().flatten().unwrap_or(0);
+            let (green, orange, red) = (get("green"), get("orange"), get("red"));
+            if green + orange + red == 0 {
+                error!("a light cycle needs a positive length");
+                return None;
+            }
+            Some(TrafficControl::Light(TrafficLightSchedule::from_basic(
+                green as usize,
+                orange as usize,
+                red as usize,
+                get("offset") as usize,
+            )))
+        }
+        _ => {
+            error!("expected a traffic control, got a {}", v.type_name());
+            None
+        }
+    }
+}
+
+/// Controls of each lane from the value returned by the script
+fn parse_controls(groups: &[Vec<LaneID>], v: Vec<Value>) -> Option<LaneControls> {
+    let mut controls = vec![];
+    for (group, entry) in groups.iter().zip(v.iter()) {
+        match entry {
+            Value::Table(t) if t.contains_key(1).unwrap_or(false) => {
+                for (i, &lane) in group.iter().enumerate() {
+                    let c = parse_control(&t.get::<_, Value>(i + 1).ok()?)?;
+                    controls.push((lane, c));
+                }
+            }
+            _ => {
+                let c = parse_control(entry)?;
+                controls.extend(group.iter().map(|&lane| (lane, c)));
+            }
+        }
+    }
+    Some(controls)
+}
+
+/// Controls given by the script's `schedule` function when the intersection changes
+pub fn schedule(
+    scripts: &LightScripts,
+    script: &str,
+    inter: &Intersection,
+    groups: &[Vec<LaneID>],
+    lanes: &Lanes,
+) -> Option<LaneControls> {
+    scripts.with(script, |l| {
+        let t = groups_table(l, inter, groups, lanes, None).ok()?;
+        let v: Vec<Value> = mods::call_with(l, "schedule", (t, inter.id.as_ffi()))?;
+        parse_controls(groups, v)
+    })
+}
+
+/// Controls given by the script's `tick` function, if it defines one and it returned some
+pub fn tick(
+    scripts: &LightScripts,
+    script: &str,
+    inter: &Intersection,
+    groups: &[Vec<LaneID>],
+    lanes: &Lanes,
+    time: f64,
+    waiting: &[u32],
+) -> Option<LaneControls> {
+    scripts.with(script, |l| {
+        if !mods::is_defined(l, "tick") {
+            return None;
+        }
+        let t = groups_table(l, inter, groups, lanes, Some(waiting)).ok()?;
+        let v: Option<Vec<Value>> = mods::call_with(l, "tick", (time, t, inter.id.as_ffi()))?;
+        parse_controls(groups, v?)
+    })
+}
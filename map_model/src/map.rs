@@ -1,6 +1,7 @@
+use crate::light_script::{LaneControls, LightScripts};
 use crate::{
-    House, HouseID, Intersection, IntersectionID, Lane, LaneID, LaneKind, LanePattern,
-    ParkingSpotID, ParkingSpots, Road, RoadID, RoadSegmentKind, SpatialMap,
+    BuildingKind, House, HouseID, Intersection, IntersectionID, Lane, LaneID, LaneKind,
+    LanePattern, ParkingSpotID, ParkingSpots, Road, RoadID, RoadSegmentKind, SpatialMap,
 };
 use geom::splines::Spline;
 use geom::Vec2;
@@ -13,6 +14,11 @@ pub type Roads = DenseSlotMap<RoadID, Road>;
 pub type Lanes = DenseSlotMap<LaneID, Lane>;
 pub type Intersections = DenseSlotMap<IntersectionID, Intersection>;
 pub type Houses = DenseSlotMap<HouseID, House>;
+
+/// Size of the lots along the roads where houses are made, in meters
+const LOT_FRONTAGE: f32 = 30.0;
+const LOT_DEPTH: f32 = 25.0;
+
 #[derive(Debug, Clone, Copy)]
 pub enum ProjectKind {
     Inter(IntersectionID),
@@ -34,6 +40,7 @@ pub struct Map {
     pub(crate) houses: Houses,
     pub(crate) spatial_map: SpatialMap,
     pub parking: ParkingSpots,
+    pub(crate) light_scripts: LightScripts,
     pub dirty: bool,
 }
 
@@ -51,6 +58,7 @@ impl Map {
             intersections: Intersections::default(),
             parking: ParkingSpots::default(),
             houses: Houses::default(),
+            light_scripts: LightScripts::default(),
             dirty: true,
             spatial_map: SpatialMap::default(),
         }
@@ -62,10 +70,25 @@ impl Map {
         f(inter);
 
         let inter = &mut self.intersections[id];
-        inter.update_traffic_control(&mut self.lanes, &self.roads);
+        inter.update_traffic_control(&mut self.lanes, &self.roads, &self.light_scripts);
         inter.update_turns(&self.lanes, &self.roads);
     }
 
+    /// Gives new controls to incoming lanes of the intersection, its turns are kept
+    pub fn set_lane_controls(&mut self, id: IntersectionID, controls: LaneControls) {
+        let inter = unwrap_or!(self.intersections.get_mut(id), return);
+        for (lane, control) in controls {
+            if let Some(l) = self.lanes.get_mut(lane) {
+                // Signs are part of the map mesh
+                if std::mem::discriminant(&l.control) != std::mem::discriminant(&control) {
+                    self.dirty = true;
+                }
+                l.control = control;
+            }
+        }
+        inter.update_turn_controls(&self.lanes, &self.roads);
+    }
+
     fn invalidate(&mut self, id: IntersectionID) {
         info!("invalidate {:?}", id);
 
@@ -85,7 +108,7 @@ impl Map {
         }
 
         let inter = &mut self.intersections[id];
-        inter.update_traffic_control(&mut self.lanes, &self.roads);
+        inter.update_traffic_control(&mut self.lanes, &self.roads, &self.light_scripts);
         inter.update_turns(&self.lanes, &self.roads);
         inter.update_polygon(&self.roads);
         self.spatial_map.update_inter(inter);
@@ -203,14 +226,23 @@ impl Map {
         let l = r.generated_points.length();
         let w = r.width * 0.5;
 
+        // Wide roads are lined with shops
+        let kind = if r.lanes_iter().filter(|(_, kind)| kind.vehicles()).count() > 4 {
+            BuildingKind::Commercial
+        } else {
+            BuildingKind::Residential
+        };
+
         for (pos, dir) in r
             .generated_points
-            .points_dirs_along((0..(l / 30.0) as usize).map(|i| i as f32 * 30.0 + 15.0))
+            .points_dirs_along(
+                (0..(l / LOT_FRONTAGE) as usize).map(|i| (i as f32 + 0.5) * LOT_FRONTAGE),
+            )
             .collect::<Vec<_>>()
         {
             let p = dir.perpendicular();
-            House::try_make(self, pos + p * (w + 3.0), p);
-            House::try_make(self, pos - p * (w + 3.0), -p);
+            House::try_make(self, pos + p * (w + 3.0), p, LOT_FRONTAGE, LOT_DEPTH, kind);
+            House::try_make(self, pos - p * (w + 3.0), -p, LOT_FRONTAGE, LOT_DEPTH, kind);
         }
     }
 
@@ -315,6 +347,9 @@ impl Map {
     pub fn houses(&self) -> &Houses {
         &self.houses
     }
+    pub fn light_scripts(&self) -> &LightScripts {
+        &self.light_scripts
+    }
     pub fn spatial_map(&self) -> &SpatialMap {
         &self.spatial_map
     }
@@ -2,6 +2,7 @@ use crate::{Lane, LaneID, LaneKind, CROSSWALK_WIDTH};
 use geom::Vec2;
 use ordered_float::OrderedFloat;
 use rand::seq::IteratorRandom;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use slotmap::{new_key_type, SecondaryMap, SlotMap};
 use std::collections::HashSet;
@@ -30,8 +31,8 @@ impl ParkingSpots {
         self.spots.get(spot)
     }
 
-    pub fn random_spot(&self) -> Option<ParkingSpotID> {
-        self.spots.keys().choose(&mut rand::thread_rng())
+    pub fn random_spot<R: Rng>(&self, r: &mut R) -> Option<ParkingSpotID> {
+        self.spots.keys().choose(r)
     }
 
     pub fn remove_spots(&mut self, lane: LaneID) {
@@ -1,5 +1,15 @@
-use crate::{Houses, Intersections, Lanes, Map, ParkingSpots, Roads, SpatialMap};
+use crate::light_script::LightScripts;
+use crate::{
+    BuildingKind, House, HouseID, Houses, Intersections, Lanes, Map, ParkingSpots, Roads,
+    SpatialMap,
+};
+use geom::polygon::Polygon;
 use serde::{Deserialize, Serialize};
+use slotmap::DenseSlotMap;
+
+/// Version of the `SerializedMap` format, saved along with it.
+/// Maps saved before versioning are read as `SerializedMapV0`.
+pub const MAP_FORMAT_VERSION: u32 = 1;
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct SerializedMap {
@@ -30,6 +40,8 @@ impl Into<Map> for SerializedMap {
     fn into(mut self) -> Map {
         for inter in self.intersections.values_mut() {
             inter.update_polygon(&self.roads);
+            inter.update_turn_controls(&self.lanes, &self.roads);
+            inter.update_conflicts();
         }
 
         let spatial_map = mk_spatial_map(&self);
@@ -40,11 +52,67 @@ impl Into<Map> for SerializedMap {
             houses: self.houses,
             spatial_map,
             parking: self.parking,
+            light_scripts: LightScripts::default(),
             dirty: false,
         }
     }
 }
 
+/// Building as saved before they had a kind, floors and an entrance
+#[derive(Deserialize)]
+pub struct HouseV0 {
+    pub id: HouseID,
+    pub exterior: Polygon,
+}
+
+/// Map as saved before versioning
+#[derive(Deserialize)]
+pub struct SerializedMapV0 {
+    roads: Roads,
+    intersections: Intersections,
+    houses: DenseSlotMap<HouseID, HouseV0>,
+    lanes: Lanes,
+    parking: ParkingSpots,
+}
+
+/// Old buildings become one-floor residential buildings entered from the closest road.
+/// Their ids change.
+impl From<SerializedMapV0> for SerializedMap {
+    fn from(m: SerializedMapV0) -> Self {
+        let mut houses = Houses::with_key();
+        for (_, old) in m.houses {
+            let center = old.exterior.bcircle().center;
+            let front = m
+                .roads
+                .values()
+                .map(|r| r.project(center))
+                .min_by(|a, b| {
+                    a.distance2(center)
+                        .partial_cmp(&b.distance2(center))
+                        .unwrap()
+                })
+                .unwrap_or(center);
+            let entrance = old.exterior.project(front);
+
+            houses.insert_with_key(|id| House {
+                id,
+                exterior: old.exterior,
+                kind: BuildingKind::Residential,
+                floors: 1,
+                entrance,
+            });
+        }
+
+        Self {
+            roads: m.roads,
+            intersections: m.intersections,
+            houses,
+            lanes: m.lanes,
+            parking: m.parking,
+        }
+    }
+}
+
 fn mk_spatial_map(m: &SerializedMap) -> SpatialMap {
     let mut sm = SpatialMap::default();
     for h in m.houses.values() {
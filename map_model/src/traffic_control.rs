@@ -1,11 +1,13 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrafficBehavior {
     RED,
     ORANGE,
     GREEN,
     STOP,
+    /// Only vehicles with a reservation for their turn may enter
+    RESERVATION,
 }
 
 impl TrafficBehavior {
@@ -23,6 +25,9 @@ pub struct TrafficLightSchedule {
     offset: usize,
 }
 
+/// Time given to pedestrians to finish crossing before the vehicles get the green light, in seconds
+const PEDESTRIAN_CLEARANCE: usize = 6;
+
 impl TrafficLightSchedule {
     pub fn from_basic(green: usize, orange: usize, red: usize, offset: usize) -> Self {
         Self {
@@ -33,6 +38,19 @@ impl TrafficLightSchedule {
             offset,
         }
     }
+
+    /// Schedule of the pedestrians crossing the lanes following this schedule.
+    /// They walk while the vehicles have a red light, with orange being a flashing "don't walk".
+    pub fn pedestrian(&self) -> Self {
+        let clearance = PEDESTRIAN_CLEARANCE.min(self.red / 2);
+        Self {
+            period: self.period,
+            green: self.red - clearance,
+            orange: clearance,
+            red: self.green + self.orange,
+            offset: (self.offset + self.period - self.green - self.orange) % self.period,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -40,9 +58,25 @@ pub enum TrafficControl {
     Always,
     Light(TrafficLightSchedule),
     StopSign,
+    /// Space-time slots granted by the intersection manager
+    Reservation,
+}
+
+impl Default for TrafficControl {
+    fn default() -> Self {
+        TrafficControl::Always
+    }
 }
 
 impl TrafficControl {
+    /// Control of a crosswalk across lanes controlled by `self`
+    pub fn pedestrian(&self) -> TrafficControl {
+        match self {
+            TrafficControl::Light(schedule) => TrafficControl::Light(schedule.pedestrian()),
+            _ => TrafficControl::Always,
+        }
+    }
+
     pub fn is_always(&self) -> bool {
         matches!(self, TrafficControl::Always)
     }
@@ -55,6 +89,10 @@ impl TrafficControl {
         matches!(self, TrafficControl::Light(_))
     }
 
+    pub fn is_reservation(&self) -> bool {
+        matches!(self, TrafficControl::Reservation)
+    }
+
     pub fn get_behavior(&self, time_seconds: u64) -> TrafficBehavior {
         match self {
             TrafficControl::Always => TrafficBehavior::GREEN,
@@ -69,6 +107,7 @@ impl TrafficControl {
                 }
             }
             TrafficControl::StopSign => TrafficBehavior::STOP,
+            TrafficControl::Reservation => TrafficBehavior::RESERVATION,
         }
     }
 }
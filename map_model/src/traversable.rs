@@ -1,4 +1,4 @@
-use crate::{IntersectionID, LaneID, Lanes, Map, TurnID};
+use crate::{IntersectionID, LaneID, Lanes, Map, TrafficBehavior, TurnID};
 use geom::polyline::PolyLine;
 use imgui_inspect::imgui;
 use imgui_inspect_derive::*;
@@ -63,6 +63,20 @@ impl Traversable {
         }
     }
 
+    /// Whether a pedestrian may start walking on it, false on crosswalks not showing "walk"
+    pub fn can_enter(&self, time: u64, m: &Map) -> bool {
+        match self.kind {
+            TraverseKind::Lane(_) => true,
+            TraverseKind::Turn(id) => m
+                .intersections
+                .get(id.parent)
+                .and_then(|inter| inter.find_turn(id))
+                .map_or(true, |turn| {
+                    matches!(turn.control.get_behavior(time), TrafficBehavior::GREEN)
+                }),
+        }
+    }
+
     pub fn is_valid(&self, m: &Map) -> bool {
         let lanes = &m.lanes;
         match self.kind {
@@ -1,4 +1,4 @@
-use crate::{IntersectionID, LaneID, Lanes};
+use crate::{IntersectionID, LaneID, Lanes, TrafficControl};
 use geom::polyline::PolyLine;
 use geom::splines::Spline;
 use geom::Vec2;
@@ -41,6 +41,10 @@ pub struct Turn {
     pub id: TurnID,
     pub points: PolyLine,
     pub kind: TurnKind,
+    /// Pedestrian signal of crosswalks, always for the other turns.
+    /// Not saved, as it follows the lanes: maps from before it existed load the same way.
+    #[serde(skip)]
+    pub control: TrafficControl,
 }
 
 const TURN_ANG_ADD: f32 = 0.29;
@@ -54,6 +58,7 @@ impl Turn {
             id,
             points: PolyLine::new(vec![Vec2::ZERO; N_SPLINE + 2]),
             kind,
+            control: TrafficControl::Always,
         }
     }
 
@@ -1,6 +1,5 @@
-use geom::polygon::Polygon;
 use lazy_static::*;
-use mlua::{FromLuaMulti, Lua, TableExt};
+use mlua::{FromLuaMulti, Function, HookTriggers, Lua, StdLib, TableExt, ToLuaMulti};
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::fs::File;
@@ -10,8 +9,10 @@ use std::time::SystemTime;
 
 pub use mlua;
 
+mod packages;
 mod stdlib;
-use std::path::Path;
+pub use packages::{discover, ModManifest, ModPackage, MODS_DIR};
+use std::path::{Path, PathBuf};
 pub use stdlib::*;
 
 trait ResultExt<T> {
@@ -31,30 +32,263 @@ struct LuaFile {
 }
 
 pub struct Mods {
-    files: RwLock<HashMap<&'static str, Mutex<LuaFile>>>, // Mutex is very important to guarentee sync
+    files: RwLock<HashMap<PathBuf, Mutex<LuaFile>>>, // Mutex is very important to guarentee sync
+    /// Scripts evaluated once, see `with_script`
+    scripts: ScriptCache,
 }
 
 impl Mods {
     fn new() -> Self {
         Self {
             files: RwLock::new(HashMap::new()),
+            scripts: ScriptCache::default(),
         }
     }
 }
 
 lazy_static! {
     static ref MODS: Mods = Mods::new();
+    static ref PACKAGES: RwLock<Vec<ModPackage>> = RwLock::new(discover(MODS_DIR));
+}
+
+/// The mod packages found in `MODS_DIR`, in load order
+pub fn packages() -> Vec<ModPackage> {
+    PACKAGES.read().unwrap().clone()
+}
+
+/// Looks for packages again, the known ones keep their enabled state
+pub fn rescan_packages() {
+    let mut packages = PACKAGES.write().unwrap();
+    let mut found = discover(MODS_DIR);
+    for p in &mut found {
+        if let Some(old) = packages
+            .iter()
+            .find(|old| old.manifest.name == p.manifest.name)
+        {
+            p.enabled = old.enabled;
+        }
+    }
+    packages::propagate_missing(&mut found);
+    *packages = found;
+}
+
+pub fn set_mod_enabled(name: &str, enabled: bool) {
+    let mut packages = PACKAGES.write().unwrap();
+    for p in packages.iter_mut().filter(|p| p.manifest.name == name) {
+        p.enabled = enabled;
+    }
+    packages::propagate_missing(&mut packages);
+}
+
+/// House generation script of the last active package declaring one, `lua/house.lua` otherwise
+pub fn house_script() -> PathBuf {
+    PACKAGES
+        .read()
+        .unwrap()
+        .iter()
+        .rev()
+        .filter(|p| p.active())
+        .find_map(|p| p.manifest.house.clone())
+        .unwrap_or_else(|| PathBuf::from("lua/house.lua"))
+}
+
+/// Scenarios of the active packages, in load order
+pub fn scenario_scripts() -> Vec<PathBuf> {
+    PACKAGES
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|p| p.active())
+        .flat_map(|p| p.manifest.scenarios.iter().cloned())
+        .collect()
+}
+
+/// Vehicle type scripts of the active packages, in load order
+pub fn vehicle_scripts() -> Vec<PathBuf> {
+    PACKAGES
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|p| p.active())
+        .filter_map(|p| p.manifest.vehicles.clone())
+        .collect()
+}
+
+/// Maximum memory an interpreter can allocate, in bytes
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// The budget is checked every `HOOK_INTERVAL` instructions
+const HOOK_INTERVAL: u32 = 10_000;
+
+/// Number of checks a single call from Rust can last before being interrupted,
+/// so about 100 million instructions
+const MAX_HOOK_CALLS: u32 = 10_000;
+
+const BUDGET_KEY: &str = "__instruction_budget";
+
+/// Directory of the built-in scripts, where `require` looks for modules
+const LUA_DIR: &str = "lua";
+
+/// Removes what could reach the filesystem or load native code.
+/// `require` only finds Lua source through `__read_module`, which reads from fixed directories.
+const SANDBOX_PRELUDE: &str = r#"
+package.path = nil
+package.cpath = nil
+package.loadlib = nil
+package.searchpath = nil
+dofile = nil
+loadfile = nil
+local load = load
+local read_module = __read_module
+__read_module = nil
+package.searchers = {
+    package.searchers[1],
+    function(name)
+        local source, path = read_module(name)
+        if not source then
+            return path
+        end
+        local chunk, err = load(source, "@" .. path, "t")
+        if not chunk then
+            error(err, 2)
+        end
+        return chunk, path
+    end,
+}
+_G.load = function(chunk, name, _, ...)
+    return load(chunk, name, "t", ...)
+end
+"#;
+
+/// Reads the module `name`, e.g. `a.b` for `a/b.lua`, from the first of `roots` having it.
+/// Names are made of letters, digits and `_` separated by dots, so they can't leave the roots.
+/// The error lists the files tried, as `require` expects from searchers.
+fn read_module(roots: &[PathBuf], name: &str) -> Result<(String, PathBuf), String> {
+    let valid = name.split('.').all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+    if !valid {
+        return Err(format!("invalid module name '{}'", name));
+    }
+
+    let rel = PathBuf::from(name.replace('.', "/") + ".lua");
+    let mut tried = vec![];
+    for root in roots {
+        let path = root.join(&rel);
+        match std::fs::read_to_string(&path) {
+            Ok(source) => return Ok((source, path)),
+            Err(_) => tried.push(format!("no file '{}'", path.display())),
+        }
+    }
+    Err(tried.join("\n\t"))
+}
+
+/// Creates an interpreter with only the safe standard libraries, a memory limit and an
+/// instruction budget per call from Rust. `require` loads modules from `roots`.
+fn sandbox(roots: Vec<PathBuf>) -> Option<Lua> {
+    // `io`, `os` and `debug` are left out
+    let lua = Lua::new_with(
+        StdLib::COROUTINE
+            | StdLib::TABLE
+            | StdLib::STRING
+            | StdLib::UTF8
+            | StdLib::MATH
+            | StdLib::PACKAGE,
+    );
+    lua.set_memory_limit(MEMORY_LIMIT).ok_print()?;
+    lua.set_named_registry_value(BUDGET_KEY, 0u32).ok_print()?;
+    lua.set_hook(
+        HookTriggers {
+            every_nth_instruction: Some(HOOK_INTERVAL),
+            ..Default::default()
+        },
+        |lua: &Lua, _| {
+            let n: u32 = lua.named_registry_value(BUDGET_KEY)?;
+            if n >= MAX_HOOK_CALLS {
+                return Err(mlua::Error::RuntimeError(
+                    "script exceeded its instruction budget".to_string(),
+                ));
+            }
+            lua.set_named_registry_value(BUDGET_KEY, n + 1)
+        },
+    );
+    let read = lua
+        .create_function(move |_, name: String| {
+            Ok(match read_module(&roots, &name) {
+                Ok((source, path)) => (Some(source), path.display().to_string()),
+                Err(e) => (None, e),
+            })
+        })
+        .ok_print()?;
+    lua.globals().set("__read_module", read).ok_print()?;
+    lua.load(SANDBOX_PRELUDE).exec().ok_print()?;
+    Some(lua)
+}
+
+/// Where `require` looks for modules of the given script: the directory of its package if it
+/// is an entry point of one, then `lua/`
+fn module_roots(script: &Path) -> Vec<PathBuf> {
+    let mut roots: Vec<PathBuf> = PACKAGES
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|p| script.starts_with(&p.dir))
+        .map(|p| p.dir.clone())
+        .collect();
+    roots.push(PathBuf::from(LUA_DIR));
+    roots
+}
+
+/// Runs `f` with a fresh instruction budget
+fn budgeted<R>(l: &Lua, f: impl FnOnce() -> R) -> R {
+    l.set_named_registry_value(BUDGET_KEY, 0u32).ok_print();
+    f()
 }
 
 pub fn call_f<'a, R: FromLuaMulti<'a>>(l: &'a Lua, f: &str) -> Option<R> {
-    l.globals().call_function(f, ()).ok_print()
+    budgeted(l, || l.globals().call_function(f, ()).ok_print())
 }
 
 pub fn eval_f(l: &Lua, f: &str) -> Option<()> {
     call_f(l, f)
 }
 
+/// Calls the global function `f` with the given arguments, does nothing if the script doesn't define it
+pub fn call_if_defined<'a, A: ToLuaMulti<'a>>(l: &'a Lua, f: &str, args: A) -> Option<()> {
+    let func: Option<Function> = l.globals().get(f).ok_print()?;
+    call_function(l, &func?, args)
+}
+
+/// Calls `f` with the given arguments and returns its result, for functions that aren't globals
+pub fn call_function<'a, A: ToLuaMulti<'a>, R: FromLuaMulti<'a>>(
+    l: &'a Lua,
+    f: &Function<'a>,
+    args: A,
+) -> Option<R> {
+    budgeted(l, || f.call(args).ok_print())
+}
+
+/// Calls the global function `f` with the given arguments and returns its result
+pub fn call_with<'a, A: ToLuaMulti<'a>, R: FromLuaMulti<'a>>(
+    l: &'a Lua,
+    f: &str,
+    args: A,
+) -> Option<R> {
+    budgeted(l, || l.globals().call_function(f, args).ok_print())
+}
+
+/// Whether the script defines the global function `f`
+pub fn is_defined(l: &Lua, f: &str) -> bool {
+    matches!(l.globals().get::<_, Option<Function>>(f), Ok(Some(_)))
+}
+
 pub fn load<P: AsRef<Path>>(name: P) -> Option<Lua> {
+    load_with(name, |_| {})
+}
+
+/// Same as `load`, but `f` is called on the interpreter right before the script is evaluated,
+/// so globals it sets are visible at the script's top level.
+pub fn load_with<P: AsRef<Path>, F: FnOnce(&Lua)>(name: P, f: F) -> Option<Lua> {
     let name = name.as_ref();
     let mut data_file = File::open(name)
         .map_err(|err| log::error!("Could not open `{:?}`, {}", name, err))
@@ -62,21 +296,23 @@ pub fn load<P: AsRef<Path>>(name: P) -> Option<Lua> {
 
     let mut data = String::new();
     data_file.read_to_string(&mut data).ok()?;
-    let lua = unsafe { Lua::unsafe_new() };
-    lua.load(r#"package.path = "lua/?.lua;?.lua""#)
-        .eval()
-        .ok_print()?;
+    let lua = sandbox(module_roots(name))?;
     add_std(&lua);
-    lua.load(&data).eval().ok_print()?;
+    lua.globals()
+        .set("params", lua.create_table().ok_print()?)
+        .ok_print()?;
+    f(&lua);
+    budgeted(&lua, || lua.load(&data).eval::<()>().ok_print())?;
     Some(lua)
 }
 
-pub fn with_init<F: FnOnce(&Lua), T: for<'a> FromLuaMulti<'a>>(
+pub fn with_init<F: FnOnce(&Lua), T: for<'a> FromLuaMulti<'a>, P: AsRef<Path>>(
     f: F,
-    name: &'static str,
+    name: P,
 ) -> Option<T> {
+    let name = name.as_ref();
     let mut data_file = File::open(name)
-        .map_err(|err| log::error!("Could not open `{}`, {}", name, err))
+        .map_err(|err| log::error!("Could not open `{:?}`, {}", name, err))
         .ok()?;
 
     let time = data_file.metadata().ok_print()?.modified().ok_print()?;
@@ -89,19 +325,20 @@ pub fn with_init<F: FnOnce(&Lua), T: for<'a> FromLuaMulti<'a>>(
             Some(sf) => {
                 let luaf = sf.lock().unwrap();
                 if luaf.time == time {
-                    return luaf.lua.load(&luaf.source).eval().ok_print();
+                    let l = &luaf.lua;
+                    return budgeted(l, || l.load(&luaf.source).eval().ok_print());
                 }
-                log::info!("re-loading {}", name);
+                log::info!("re-loading {:?}", name);
             }
             None => {
-                log::info!("loading {}", name);
+                log::info!("loading {:?}", name);
             }
         }
     }
 
     let mut data = String::new();
     data_file.read_to_string(&mut data).ok()?;
-    let lua = unsafe { Lua::unsafe_new() };
+    let lua = sandbox(module_roots(name))?;
     add_std(&lua);
     f(&lua);
     let f = Mutex::new(LuaFile {
@@ -110,17 +347,86 @@ pub fn with_init<F: FnOnce(&Lua), T: for<'a> FromLuaMulti<'a>>(
         lua,
     });
 
-    MODS.files.write().unwrap().insert(name, f);
+    MODS.files.write().unwrap().insert(name.to_path_buf(), f);
 
     let guard = MODS.files.read().unwrap();
     let luaf = guard.get(name).unwrap().lock().unwrap();
-    luaf.lua.load(&luaf.source).eval().ok_print()
+    let l = &luaf.lua;
+    budgeted(l, || l.load(&luaf.source).eval().ok_print())
 }
 
-pub fn eval_script<T: for<'a> FromLuaMulti<'a>>(name: &'static str) -> Option<T> {
-    with_init(|_| {}, name)
+/// Runs `f` on the interpreter of the script `name`. Unlike `with_init`, the script is only
+/// evaluated when first used or after being modified, so it can keep state in its globals
+/// and define functions for `f` to call.
+pub fn with_script<P: AsRef<Path>, R, F: FnOnce(&Lua) -> Option<R>>(name: P, f: F) -> Option<R> {
+    let name = name.as_ref();
+    MODS.scripts.refresh_one(name);
+    MODS.scripts.with(name, f)
 }
 
-pub fn gen_house() -> Option<Polygon> {
-    eval_script::<LuaPolygon>("lua/test.lua").map(|x| x.0)
+struct CachedScript {
+    time: SystemTime,
+    lua: Lua,
+}
+
+/// Interpreters of scripts evaluated once, keeping their state in their globals between calls.
+/// Using a file doesn't check whether it was modified, `refresh` has to be called for that,
+/// so that owners calling many times in a row only look at the files once.
+#[derive(Default)]
+pub struct ScriptCache {
+    scripts: RwLock<HashMap<PathBuf, Mutex<CachedScript>>>,
+}
+
+impl ScriptCache {
+    /// Runs `f` on the interpreter of `name`, evaluating the script if it isn't loaded yet
+    pub fn with<P: AsRef<Path>, R, F: FnOnce(&Lua) -> Option<R>>(
+        &self,
+        name: P,
+        f: F,
+    ) -> Option<R> {
+        let name = name.as_ref();
+        {
+            let guard = self.scripts.read().unwrap();
+            if let Some(script) = guard.get(name) {
+                return f(&script.lock().unwrap().lua);
+            }
+        }
+
+        log::info!("loading {:?}", name);
+        let time = std::fs::metadata(name)
+            .and_then(|m| m.modified())
+            .map_err(|err| log::error!("Could not open `{:?}`, {}", name, err))
+            .ok()?;
+        let lua = load(name)?;
+        let r = f(&lua);
+        self.scripts
+            .write()
+            .unwrap()
+            .insert(name.to_path_buf(), Mutex::new(CachedScript { time, lua }));
+        r
+    }
+
+    /// Forgets the scripts modified since they were loaded, they are evaluated again when next used
+    pub fn refresh(&self) {
+        let names: Vec<PathBuf> = self.scripts.read().unwrap().keys().cloned().collect();
+        for name in names {
+            self.refresh_one(&name);
+        }
+    }
+
+    fn refresh_one(&self, name: &Path) {
+        let time = std::fs::metadata(name).and_then(|m| m.modified()).ok();
+        let mut guard = self.scripts.write().unwrap();
+        let stale = guard
+            .get(name)
+            .map_or(false, |script| Some(script.lock().unwrap().time) != time);
+        if stale {
+            log::info!("re-loading {:?}", name);
+            guard.remove(name);
+        }
+    }
+}
+
+pub fn eval_script<T: for<'a> FromLuaMulti<'a>, P: AsRef<Path>>(name: P) -> Option<T> {
+    with_init(|_| {}, name)
 }
@@ -0,0 +1,178 @@
+//! Mod packages: directories of `MODS_DIR` containing a `manifest.lua` such as
+//!
+//! ```lua
+//! return {
+//!     name = "suburbs",
+//!     version = "0.1.0",
+//!     dependencies = { "base_roads" },
+//!     house = "house.lua",
+//!     scenarios = { "scenarios/commute.lua" },
+//!     vehicles = "vehicles.lua",
+//! }
+//! ```
+//!
+//! Paths are relative to the package directory and can't leave it. Entry points of later packages in the load
+//! order take precedence over earlier ones and over the built-in `lua/` scripts.
+
+use crate::ResultExt;
+use mlua::Table;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+
+pub const MODS_DIR: &str = "mods";
+const MANIFEST: &str = "manifest.lua";
+
+#[derive(Clone, Debug)]
+pub struct ModManifest {
+    pub name: String,
+    pub version: String,
+    /// Names of the packages that must be loaded before this one
+    pub dependencies: Vec<String>,
+    pub house: Option<PathBuf>,
+    pub scenarios: Vec<PathBuf>,
+    pub vehicles: Option<PathBuf>,
+}
+
+impl ModManifest {
+    fn from_lua(t: Table, dir: &Path) -> mlua::Result<Self> {
+        let path = |p: String| inside(dir, &p);
+        Ok(Self {
+            name: t.get("name")?,
+            version: t
+                .get::<_, Option<String>>("version")?
+                .unwrap_or_else(|| "0.0.0".to_string()),
+            dependencies: t
+                .get::<_, Option<Vec<String>>>("dependencies")?
+                .unwrap_or_default(),
+            house: t.get::<_, Option<String>>("house")?.map(path).transpose()?,
+            scenarios: t
+                .get::<_, Option<Vec<String>>>("scenarios")?
+                .unwrap_or_default()
+                .into_iter()
+                .map(path)
+                .collect::<mlua::Result<_>>()?,
+            vehicles: t
+                .get::<_, Option<String>>("vehicles")?
+                .map(path)
+                .transpose()?,
+        })
+    }
+
+    fn load(dir: &Path) -> Option<Self> {
+        let lua = crate::sandbox(vec![PathBuf::from(crate::LUA_DIR)])?;
+        let source = std::fs::read_to_string(dir.join(MANIFEST)).ok_print()?;
+        let t: Table = crate::budgeted(&lua, || lua.load(&source).eval().ok_print())?;
+        Self::from_lua(t, dir)
+            .map_err(|e| log::error!("invalid manifest in {:?}: {}", dir, e))
+            .ok()
+    }
+}
+
+/// `p` joined to `dir`, as long as it is relative and stays in it
+fn inside(dir: &Path, p: &str) -> mlua::Result<PathBuf> {
+    let rel = Path::new(p);
+    if !rel
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(mlua::Error::RuntimeError(format!(
+            "{} is not a path inside the package",
+            p
+        )));
+    }
+    Ok(dir.join(rel))
+}
+
+#[derive(Clone, Debug)]
+pub struct ModPackage {
+    pub dir: PathBuf,
+    pub manifest: ModManifest,
+    pub enabled: bool,
+    /// Set when a dependency is missing, disabled or part of a cycle
+    pub missing_dependencies: bool,
+    /// A dependency is missing or part of a cycle, known after discovery
+    unresolved: bool,
+}
+
+impl ModPackage {
+    /// Whether the package's entry points are used
+    pub fn active(&self) -> bool {
+        self.enabled && !self.missing_dependencies
+    }
+}
+
+/// Finds the packages of `dir` and sorts them so that each comes after its dependencies,
+/// packages with no order between them are sorted by name
+pub fn discover<P: AsRef<Path>>(dir: P) -> Vec<ModPackage> {
+    let mut found: Vec<ModPackage> = std::fs::read_dir(dir.as_ref())
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.ok())
+        .map(|entry| entry.path())
+        .filter(|p| p.join(MANIFEST).is_file())
+        .filter_map(|dir| {
+            let manifest = ModManifest::load(&dir)?;
+            Some(ModPackage {
+                dir,
+                manifest,
+                enabled: true,
+                missing_dependencies: false,
+                unresolved: false,
+            })
+        })
+        .collect();
+
+    found.sort_by(|a, b| a.manifest.name.cmp(&b.manifest.name));
+    found.dedup_by(|b, a| {
+        let dup = a.manifest.name == b.manifest.name;
+        if dup {
+            log::error!(
+                "mod {} is declared twice, ignoring {:?}",
+                b.manifest.name,
+                b.dir
+            );
+        }
+        dup
+    });
+
+    let mut sorted: Vec<ModPackage> = Vec::with_capacity(found.len());
+    let mut placed: HashSet<String> = HashSet::new();
+    while !found.is_empty() {
+        let ready = found
+            .iter()
+            .position(|p| p.manifest.dependencies.iter().all(|d| placed.contains(d)));
+        match ready {
+            Some(i) => {
+                let p = found.remove(i);
+                placed.insert(p.manifest.name.clone());
+                sorted.push(p);
+            }
+            None => {
+                // What's left depends on missing packages or on a cycle
+                for mut p in found.drain(..) {
+                    log::error!(
+                        "mod {} has missing or cyclic dependencies, it won't be loaded",
+                        p.manifest.name
+                    );
+                    p.unresolved = true;
+                    sorted.push(p);
+                }
+            }
+        }
+    }
+
+    propagate_missing(&mut sorted);
+    sorted
+}
+
+/// Marks the packages depending on inactive ones, `packages` being in load order
+pub(crate) fn propagate_missing(packages: &mut [ModPackage]) {
+    let mut inactive: HashSet<String> = HashSet::new();
+    for p in packages.iter_mut() {
+        p.missing_dependencies =
+            p.unresolved || p.manifest.dependencies.iter().any(|d| inactive.contains(d));
+        if !p.active() {
+            inactive.insert(p.manifest.name.clone());
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use geom::polygon::Polygon;
 use geom::Vec2;
 use mlua::prelude::LuaResult;
-use mlua::{FromLuaMulti, Lua, MetaMethod, ToLuaMulti, UserData, UserDataMethods};
+use mlua::{AnyUserData, FromLuaMulti, Lua, MetaMethod, ToLuaMulti, UserData, UserDataMethods};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
 #[derive(Clone)]
 pub struct LuaPolygon(pub Polygon);
@@ -85,8 +87,26 @@ fn poly_rect(_: &Lua, (w, h): (f32, f32)) -> LuaResult<LuaPolygon> {
     Ok(LuaPolygon(Polygon::rect(w, h)))
 }
 
-fn rand_in(_: &Lua, (min, max): (f32, f32)) -> LuaResult<f32> {
-    Ok(min + rand::random::<f32>() * (max - min))
+/// Random generator of an interpreter, kept in its registry
+struct LuaRng(SmallRng);
+
+impl UserData for LuaRng {}
+
+const RNG_KEY: &str = "rand_in rng";
+
+/// Reseeds the generator used by `rand_in` in this interpreter.
+/// Every interpreter starts with the seed 0.
+pub fn seed_rand(l: &Lua, seed: u64) {
+    let rng = l
+        .create_userdata(LuaRng(SmallRng::seed_from_u64(seed)))
+        .unwrap();
+    l.set_named_registry_value(RNG_KEY, rng).unwrap()
+}
+
+fn rand_in(l: &Lua, (min, max): (f32, f32)) -> LuaResult<f32> {
+    let rng: AnyUserData = l.named_registry_value(RNG_KEY)?;
+    let mut rng = rng.borrow_mut::<LuaRng>()?;
+    Ok(min + rng.0.gen::<f32>() * (max - min))
 }
 
 fn vec2(_: &Lua, (x, y): (f32, f32)) -> LuaResult<LuaVec2> {
@@ -94,6 +114,7 @@ fn vec2(_: &Lua, (x, y): (f32, f32)) -> LuaResult<LuaVec2> {
 }
 
 pub fn add_std(lua: &Lua) {
+    seed_rand(lua, 0);
     add_fn(lua, "poly_rect", poly_rect);
     add_fn(lua, "rand_in", rand_in);
     add_fn(lua, "vec2", vec2);
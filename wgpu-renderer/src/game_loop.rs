@@ -1,7 +1,10 @@
 use crate::debug::DbgSplineState;
 use crate::engine::{Context, FrameContext, GfxContext};
 use crate::rendering::imgui_wrapper::{GuiRenderContext, ImguiWrapper};
-use crate::rendering::{CameraHandler, InstancedRender, MeshRenderer, RoadRenderer};
+use crate::rendering::{
+    render_emergencies, render_gridlocks, render_heatmap, render_incidents, CameraHandler,
+    InstancedRender, MeshRenderer, RoadRenderer,
+};
 use egregoria::engine_interaction::{KeyboardInfo, MouseInfo, RenderStats, TimeInfo};
 use egregoria::gui::Gui;
 use egregoria::interaction::FollowEntity;
@@ -94,10 +97,16 @@ impl State {
             ctx,
         );
 
+        render_heatmap(&mut tess, &self.state.world);
+
         self.instanced_renderer.render(&mut self.state.world, ctx);
 
         MeshRenderer::render(&mut self.state.world, &mut tess);
 
+        render_gridlocks(&mut tess, &self.state.world);
+        render_emergencies(&mut tess, &self.state.world);
+        render_incidents(&mut tess, &self.state.world);
+
         {
             let objs = crate::debug::DEBUG_OBJS.lock().unwrap();
             for (val, name, obj) in &*objs {
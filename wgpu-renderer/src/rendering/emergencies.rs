@@ -0,0 +1,35 @@
+use crate::geometry::Tesselator;
+use egregoria::physics::Transform;
+use egregoria::rendering::LinearColor;
+use egregoria::specs::prelude::*;
+use egregoria::vehicles::Emergencies;
+
+/// Above the vehicles
+const Z_EMERGENCY: f32 = 0.8;
+
+/// Shows the station and links the emergency vehicles en route to their call location
+pub fn render_emergencies(tess: &mut Tesselator, world: &World) {
+    let emergencies = world.read_resource::<Emergencies>();
+    let transforms = world.read_storage::<Transform>();
+
+    if let Some(station) = emergencies.station {
+        tess.set_color(LinearColor::BLUE);
+        tess.draw_circle(station, Z_EMERGENCY, 3.0);
+    }
+
+    tess.set_color(LinearColor {
+        a: 0.7,
+        ..LinearColor::MAGENTA
+    });
+    for call in &emergencies.calls {
+        if call.arrived.is_some() {
+            continue;
+        }
+        let trans = match call.vehicle.and_then(|e| transforms.get(e)) {
+            Some(x) => x,
+            None => continue,
+        };
+        tess.draw_stroke_circle(call.target, Z_EMERGENCY, 4.0, 0.5);
+        tess.draw_stroke(trans.position(), call.target, Z_EMERGENCY, 0.3);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::geometry::Tesselator;
+use egregoria::physics::Transform;
+use egregoria::rendering::LinearColor;
+use egregoria::specs::prelude::*;
+use egregoria::vehicles::Gridlocks;
+use geom::Vec2;
+
+/// Above the vehicles
+const Z_GRIDLOCK: f32 = 0.8;
+
+/// Circles the vehicles of each gridlock and links them in waiting order
+pub fn render_gridlocks(tess: &mut Tesselator, world: &World) {
+    let gridlocks = world.read_resource::<Gridlocks>();
+    let transforms = world.read_storage::<Transform>();
+
+    tess.set_color(LinearColor {
+        a: 0.7,
+        ..LinearColor::RED
+    });
+    for cycle in &gridlocks.cycles {
+        let positions: Vec<Vec2> = cycle
+            .iter()
+            .filter_map(|&e| transforms.get(e))
+            .map(|x| x.position())
+            .collect();
+
+        for (i, &pos) in positions.iter().enumerate() {
+            tess.draw_stroke_circle(pos, Z_GRIDLOCK, 3.5, 0.5);
+            let next = positions[(i + 1) % positions.len()];
+            tess.draw_stroke(pos, next, Z_GRIDLOCK, 0.5);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::geometry::Tesselator;
+use egregoria::gui::{Gui, HeatmapMetric};
+use egregoria::imgui::{im_str, Ui};
+use egregoria::map_interaction::Itinerary;
+use egregoria::physics::Kinematics;
+use egregoria::rendering::Color;
+use egregoria::specs::prelude::*;
+use egregoria::stats::QUEUE_SPEED;
+use egregoria::vehicles::{VehicleComponent, VehicleState, VehicleTypes};
+use map_model::{LaneID, Map, TraverseKind};
+use std::collections::HashMap;
+
+/// Above the lanes, below the crosswalks and signals
+const Z_HEATMAP: f32 = 0.245;
+
+/// Vehicles per 100m at which a lane is considered jammed
+const JAM_DENSITY: f32 = 100.0 / 7.0;
+
+/// Seconds lost crossing a lane at which it is drawn fully red by `HeatmapMetric::Delay`
+const RED_DELAY: f32 = 30.0;
+
+#[derive(Default)]
+struct LaneLoad {
+    n_vehicles: u32,
+    /// Sum of the speed ratios of the vehicles on the lane
+    speed_ratio: f32,
+    /// Sum of the speeds of the vehicles on the lane, in m/s
+    speed: f32,
+    /// Sum of the cruising speeds of the vehicles on the lane, in m/s
+    cruising_speed: f32,
+}
+
+pub fn heatmap_menu(gui: &mut Gui, ui: &Ui) {
+    if !gui.show_heatmap {
+        return;
+    }
+    let metric = &mut gui.heatmap_metric;
+    egregoria::imgui::Window::new(im_str!("Heatmap"))
+        .opened(&mut gui.show_heatmap)
+        .build(&ui, || {
+            for m in &[
+                HeatmapMetric::SpeedRatio,
+                HeatmapMetric::Density,
+                HeatmapMetric::Delay,
+            ] {
+                if ui.radio_button_bool(&im_str!("{}", m.as_ref()), *metric == *m) {
+                    *metric = *m;
+                }
+            }
+        })
+}
+
+/// Colours each vehicle lane from green (free flow) to red (congested)
+pub fn render_heatmap(tess: &mut Tesselator, world: &World) {
+    let gui = world.read_resource::<Gui>();
+    if !gui.show_heatmap {
+        return;
+    }
+    let metric = gui.heatmap_metric;
+    let map = world.read_resource::<Map>();
+    let types = world.read_resource::<VehicleTypes>();
+
+    let mut loads: HashMap<LaneID, LaneLoad> = HashMap::new();
+    for (vehicle, kin, it) in (
+        &world.read_storage::<VehicleComponent>(),
+        &world.read_storage::<Kinematics>(),
+        &world.read_storage::<Itinerary>(),
+    )
+        .join()
+    {
+        if !matches!(vehicle.state, VehicleState::Driving) {
+            continue;
+        }
+        if let Some(TraverseKind::Lane(id)) = it.get_travers().map(|x| x.kind) {
+            let cruising_speed = types[vehicle.kind].cruising_speed;
+            let speed = kin.velocity.magnitude();
+            let load = loads.entry(id).or_default();
+            load.n_vehicles += 1;
+            load.speed_ratio += (speed / cruising_speed).min(1.0);
+            load.speed += speed.min(cruising_speed);
+            load.cruising_speed += cruising_speed;
+        }
+    }
+
+    for lane in map.lanes().values() {
+        if !lane.kind.vehicles() {
+            continue;
+        }
+        let per_100m = 100.0 / lane.length.max(1.0);
+
+        let congestion = match (metric, loads.get(&lane.id)) {
+            (_, None) => 0.0,
+            (HeatmapMetric::SpeedRatio, Some(l)) => 1.0 - l.speed_ratio / l.n_vehicles as f32,
+            (HeatmapMetric::Density, Some(l)) => l.n_vehicles as f32 * per_100m / JAM_DENSITY,
+            (HeatmapMetric::Delay, Some(l)) => {
+                // Travel time at the mean speed minus the free-flow travel time. The speed is
+                // floored so that a stopped queue reads as a long delay instead of an infinite one
+                let n = l.n_vehicles as f32;
+                let mean_speed = (l.speed / n).max(QUEUE_SPEED);
+                let free_flow_speed = l.cruising_speed / n;
+                let delay = lane.length / mean_speed - lane.length / free_flow_speed;
+                delay / RED_DELAY
+            }
+        };
+
+        tess.set_color(congestion_color(congestion));
+        tess.draw_polyline(lane.points.as_slice(), Z_HEATMAP, lane.width * 0.5);
+    }
+}
+
+fn congestion_color(congestion: f32) -> Color {
+    let t = congestion.max(0.0).min(1.0);
+    if t < 0.5 {
+        Color::new(2.0 * t, 1.0, 0.0, 0.6)
+    } else {
+        Color::new(1.0, 2.0 * (1.0 - t), 0.0, 0.6)
+    }
+}
@@ -79,6 +79,7 @@ impl ImguiWrapper {
         let ui: egregoria::imgui::Ui = self.imgui.frame();
         gui.render(&ui, world);
         crate::debug::debug_menu(gui, &ui);
+        crate::rendering::heatmap_menu(gui, &ui);
 
         self.last_mouse_captured = ui.io().want_capture_mouse;
         self.last_kb_captured = ui.io().want_capture_keyboard;
@@ -0,0 +1,30 @@
+use crate::geometry::Tesselator;
+use egregoria::engine_interaction::TimeInfo;
+use egregoria::rendering::LinearColor;
+use egregoria::specs::prelude::*;
+use egregoria::stats::{IncidentKind, SafetyLog};
+
+/// Above the vehicles
+const Z_INCIDENT: f32 = 0.8;
+
+/// How long an incident stays marked, in seconds of simulated time
+const MARKER_DURATION: f64 = 10.0;
+
+/// Marks the recent collisions in red and near-misses in orange, fading out
+pub fn render_incidents(tess: &mut Tesselator, world: &World) {
+    let log = world.read_resource::<SafetyLog>();
+    let now = world.read_resource::<TimeInfo>().time;
+
+    for incident in log.since(now - MARKER_DURATION) {
+        let color = match incident.kind {
+            IncidentKind::Collision => LinearColor::RED,
+            IncidentKind::NearMiss => LinearColor::ORANGE,
+        };
+        let fade = 1.0 - ((now - incident.time) / MARKER_DURATION) as f32;
+        tess.set_color(LinearColor {
+            a: 0.8 * fade,
+            ..color
+        });
+        tess.draw_stroke_circle(incident.pos, Z_INCIDENT, 4.0, 0.5);
+    }
+}
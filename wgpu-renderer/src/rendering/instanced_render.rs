@@ -1,6 +1,6 @@
 use crate::engine::{FrameContext, GfxContext, InstanceRaw, SpriteBatchBuilder, Texture};
 use egregoria::physics::Transform;
-use egregoria::rendering::assets::AssetRender;
+use egregoria::rendering::assets::{AssetRegistry, AssetRender};
 use egregoria::specs::{Join, World, WorldExt};
 
 pub struct InstancedRender {
@@ -16,6 +16,18 @@ impl InstancedRender {
     }
 
     pub fn render(&mut self, world: &mut World, fctx: &mut FrameContext) {
+        // Load the sprites registered since the last frame
+        let registry = world.read_resource::<AssetRegistry>();
+        for path in &registry.paths()[self.texs.len().min(registry.paths().len())..] {
+            let tex = Texture::from_path(fctx.gfx, path, None).unwrap_or_else(|| {
+                log::error!("could not load sprite {}, using the car instead", path);
+                Texture::from_path(fctx.gfx, "resources/car.png", None).unwrap()
+                // Unwrap ok: File is there
+            });
+            self.texs.push(SpriteBatchBuilder::new(tex));
+        }
+        drop(registry);
+
         let transforms = world.read_component::<Transform>();
         let ass_render = world.write_component::<AssetRender>();
 
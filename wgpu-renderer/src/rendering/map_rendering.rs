@@ -6,7 +6,9 @@ use crate::geometry::Tesselator;
 use egregoria::rendering::{from_srgb, Color, LinearColor};
 use egregoria::utils::Restrict;
 use geom::vec2;
-use map_model::{Lane, LaneKind, Map, ProjectKind, TrafficBehavior, TurnKind, CROSSWALK_WIDTH};
+use map_model::{
+    Intersection, Lane, LaneKind, Map, ProjectKind, TrafficBehavior, TurnKind, CROSSWALK_WIDTH,
+};
 use std::ops::Mul;
 
 #[derive(Clone, Copy)]
@@ -156,6 +158,15 @@ impl RoadRenderer {
             return;
         }
 
+        if n.control.is_reservation() {
+            sr.color = LinearColor::WHITE;
+            sr.draw_regular_polygon(r_center, Z_SIGNAL, 0.5, 4, 0.0);
+
+            sr.color = LinearColor::BLUE;
+            sr.draw_regular_polygon(r_center, Z_SIGNAL, 0.4, 4, 0.0);
+            return;
+        }
+
         let size = 0.5; // light size
 
         sr.color = Color::gray(0.3).into();
@@ -165,7 +176,9 @@ impl RoadRenderer {
             sr.draw_circle(r_center + i as f32 * dir_perp * size, Z_SIGNAL, size * 0.5);
         }
         sr.color = match n.control.get_behavior(time) {
-            TrafficBehavior::RED | TrafficBehavior::STOP => LinearColor::RED,
+            TrafficBehavior::RED | TrafficBehavior::STOP | TrafficBehavior::RESERVATION => {
+                LinearColor::RED
+            }
             TrafficBehavior::ORANGE => LinearColor::ORANGE,
             TrafficBehavior::GREEN => LinearColor::GREEN,
         };
@@ -180,6 +193,24 @@ impl RoadRenderer {
         sr.draw_circle(r_center + offset * dir_perp, Z_SIGNAL, size * 0.5);
     }
 
+    fn render_crosswalk_signals(inter: &Intersection, sr: &mut Tesselator, time: u64) {
+        for turn in inter.turns() {
+            if !turn.kind.is_crosswalk() || !turn.control.is_light() {
+                continue;
+            }
+
+            sr.color = match turn.control.get_behavior(time) {
+                TrafficBehavior::GREEN => LinearColor::WHITE,
+                TrafficBehavior::ORANGE => LinearColor::ORANGE,
+                _ => LinearColor::RED,
+            };
+
+            for &p in &[turn.points.first(), turn.points.last()] {
+                sr.draw_circle(p, Z_SIGNAL, 0.3);
+            }
+        }
+    }
+
     fn signals_render(map: &Map, time: u64, sr: &mut Tesselator) {
         match sr.cull_rect {
             Some(rect) => {
@@ -198,11 +229,21 @@ impl RoadRenderer {
                 {
                     Self::render_lane_signals(n, sr, time);
                 }
+                for inter in map
+                    .intersections()
+                    .values()
+                    .filter(|inter| rect.contains_within(inter.pos, 20.0))
+                {
+                    Self::render_crosswalk_signals(inter, sr, time);
+                }
             }
             None => {
                 for n in map.lanes().values() {
                     Self::render_lane_signals(n, sr, time);
                 }
+                for inter in map.intersections().values() {
+                    Self::render_crosswalk_signals(inter, sr, time);
+                }
             }
         }
     }
@@ -1,10 +1,18 @@
 mod camera_handler;
+mod emergencies;
+mod gridlocks;
+mod heatmap;
 pub mod imgui_wrapper;
+mod incidents;
 mod instanced_render;
 mod map_rendering;
 mod mesh_renderer;
 
 pub use camera_handler::*;
+pub use emergencies::*;
+pub use gridlocks::*;
+pub use heatmap::*;
+pub use incidents::*;
 pub use instanced_render::*;
 pub use map_rendering::*;
 pub use mesh_renderer::*;